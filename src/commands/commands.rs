@@ -1,12 +1,20 @@
 use core::any::Any;
+use std::collections::HashMap;
 
 use btc_heritage_wallet::{
-    bitcoin::psbt::Psbt, btc_heritage::utils::bitcoin_network,
-    heritage_service_api_client::HeritageServiceConfig, online_wallet::BlockchainProviderConfig,
-    Database, DatabaseItem, DatabaseSingleItem, Heir, HeirWallet, PsbtSummary, Wallet,
+    btc_heritage::utils::bitcoin_network,
+    errors::Result,
+    heritage_service_api_client::{HeritageServiceClient, HeritageServiceConfig},
+    online_wallet::BlockchainProviderConfig,
+    AnyOnlineWallet, Database, DatabaseItem, DatabaseSingleItem, Heir, HeirWallet, PsbtSummary,
+    Wallet,
 };
 
-use crate::utils::get_fingerprints;
+use crate::{
+    psbt_analysis::{AnalyzedPsbtSummary, FeeLimits, PsbtAnalysis},
+    psbt_io::PsbtInput,
+    utils::{current_fee_rate, get_fingerprints},
+};
 
 use super::CommandExecutor;
 
@@ -41,6 +49,26 @@ pub enum Command {
         #[command(subcommand)]
         subcmd: ListAndDefault<super::subcmd_heirwallet::HeirWalletSubcmd, HeirWallet>,
     },
+    /// Commands managing Heritage Configuration templates, reusable plans of Heirs and lock durations
+    /// {n}Use this to set the same kind of Heritage Configuration on multiple wallets.
+    #[command(visible_aliases = ["templates", "t"])]
+    Template {
+        /// The name of the template to operate.
+        /// Defaults to "default" or any other name you set with the "default-name" command
+        template_name: Option<String>,
+        #[command(subcommand)]
+        subcmd: ListAndDefault<
+            super::subcmd_template::TemplateSubcmd,
+            super::subcmd_template::HeritageConfigTemplate,
+        >,
+    },
+    /// Commands managing the address book, named recipients usable as "@<NAME>" in place of an address
+    /// when sending bitcoins or spending an inheritance
+    #[command(visible_aliases = ["contact"])]
+    Contacts {
+        #[command(subcommand)]
+        subcmd: super::subcmd_contact::ContactSubcmd,
+    },
     /// Commands related to the Heritage service, mainly used to authenticate the CLI with the service.
     #[command(visible_aliases = ["svc"])]
     Service {
@@ -55,10 +83,20 @@ pub enum Command {
         set: bool,
     },
     /// Display infos on the given Partially Signed Bitcoin Transaction (PSBT)
+    /// {n}Also identifies how each input is spent, which outputs return to a local wallet,
+    /// and warns about suspicious fees.
     #[command(visible_alias = "display")]
     DisplayPsbt {
         /// The PSBT
-        psbt: Psbt,
+        #[command(flatten)]
+        psbt: PsbtInput,
+        #[command(flatten)]
+        fee_limits: FeeLimits,
+    },
+    /// Commands manipulating Partially Signed Bitcoin Transactions (PSBT) in multi-party signing workflows
+    Psbt {
+        #[command(subcommand)]
+        subcmd: super::subcmd_psbt::PsbtSubcmd,
     },
 }
 
@@ -197,6 +235,23 @@ impl super::CommandExecutor for Command {
                 let params = Box::new((db, heir_wallet_name, hsc, bcpc));
                 subcmd.execute(params).await
             }
+            Command::Template {
+                template_name,
+                subcmd,
+            } => {
+                let template_name = match template_name {
+                    Some(tn) => tn,
+                    None => {
+                        super::subcmd_template::HeritageConfigTemplate::get_default_item_name(&db)?
+                    }
+                };
+                let params = Box::new((db, template_name, hsc, bcpc));
+                subcmd.execute(params).await
+            }
+            Command::Contacts { subcmd } => {
+                let params = Box::new(db);
+                subcmd.execute(params).await
+            }
             Command::Service { subcmd } => {
                 let params = Box::new((db, hsc));
                 subcmd.execute(params).await
@@ -207,14 +262,74 @@ impl super::CommandExecutor for Command {
                 }
                 Ok(Box::new(bcpc))
             }
-            Command::DisplayPsbt { psbt } => {
-                let summary = PsbtSummary::try_from((
+            Command::DisplayPsbt { psbt, fee_limits } => {
+                let psbt = psbt.load()?;
+                let fingerprints = get_fingerprints(&db).await?;
+                let summary =
+                    PsbtSummary::try_from((&psbt, &fingerprints, bitcoin_network::get()))?;
+                let mut analysis = PsbtAnalysis::new(
                     &psbt,
-                    &get_fingerprints(&db).await?,
+                    Some(&fingerprints),
                     bitcoin_network::get(),
-                ))?;
-                Ok(Box::new(summary))
+                    &fee_limits,
+                );
+                // Compare the fee rate with the current estimate of the spending wallet, if any
+                let spending_wallet = analysis.spending_wallets().first().map(|w| w.to_string());
+                if let Some(wallet_name) = spending_wallet {
+                    let fee_limits = FeeLimits {
+                        current_fee_rate: wallet_fee_rate(&db, hsc, &wallet_name).await,
+                        ..fee_limits
+                    };
+                    if fee_limits.current_fee_rate.is_some() {
+                        analysis = PsbtAnalysis::new(
+                            &psbt,
+                            Some(&fingerprints),
+                            bitcoin_network::get(),
+                            &fee_limits,
+                        );
+                    }
+                }
+                analysis.set_contacts(&super::subcmd_contact::get_contacts(&db)?);
+                let mut labels = HashMap::new();
+                for wallet_name in analysis.spending_wallets() {
+                    labels.extend(super::subcmd_wallet_labels::get_labels(&db, wallet_name)?);
+                }
+                analysis.set_labels(&labels);
+                Ok(Box::new(AnalyzedPsbtSummary { summary, analysis }))
             }
+            Command::Psbt { subcmd } => {
+                let params = Box::new(db);
+                subcmd.execute(params).await
+            }
+        }
+    }
+}
+
+/// The current fee rate estimate of a local wallet, None if it cannot be retrieved
+async fn wallet_fee_rate(
+    db: &Database,
+    hsc: HeritageServiceConfig,
+    wallet_name: &str,
+) -> Option<f64> {
+    let wallet: Result<Wallet> = async {
+        let mut wallet = Wallet::load(db, wallet_name)?;
+        match wallet.online_wallet_mut() {
+            AnyOnlineWallet::None => (),
+            AnyOnlineWallet::Service(sb) => {
+                let service_client = HeritageServiceClient::from(hsc);
+                service_client.load_tokens_from_cache(db).await?;
+                sb.init_service_client(service_client).await?
+            }
+            AnyOnlineWallet::Local(lw) => lw.init_heritage_wallet(db.clone()).await?,
+        };
+        Ok(wallet)
+    }
+    .await;
+    match wallet {
+        Ok(wallet) => current_fee_rate(&wallet).await,
+        Err(e) => {
+            log::debug!("Could not load the wallet {wallet_name}: {e}");
+            None
         }
     }
 }
@@ -1,21 +1,30 @@
 mod commands;
 mod gargs_blockchain_provider;
 mod gargs_heritage_service;
+mod subcmd_contact;
 mod subcmd_heir;
 mod subcmd_heirwallet;
+mod subcmd_psbt;
 mod subcmd_service;
 mod subcmd_service_heir;
 mod subcmd_service_wallet;
+mod subcmd_template;
 mod subcmd_wallet;
+mod subcmd_wallet_addresses;
 mod subcmd_wallet_axpubs;
 mod subcmd_wallet_heritage_config;
+mod subcmd_wallet_labels;
 mod subcmd_wallet_ledger_policy;
+mod subcmd_wallet_transactions;
+mod subcmd_wallet_utxos;
 
 use core::any::Any;
 use std::{convert::Infallible, ops::Deref, path::PathBuf, str::FromStr};
 
 use btc_heritage_wallet::bitcoin::Network;
 
+use crate::display::OutputFormat;
+
 pub trait CommandExecutor {
     fn execute(
         self,
@@ -43,6 +52,15 @@ pub struct CliGlobalArgs {
         global = true
     )]
     pub datadir: DataDir,
+    /// Set the format used to display the result of the command.
+    #[arg(
+        long,
+        value_enum,
+        env = "HERITAGE_CLI_OUTPUT",
+        default_value_t = OutputFormat::Text,
+        global = true
+    )]
+    pub output: OutputFormat,
 }
 #[derive(Clone, Debug, clap::Parser)]
 /// The Heritage Wallet CLI
@@ -0,0 +1,181 @@
+use core::{any::Any, str::FromStr};
+use std::collections::HashMap;
+
+use btc_heritage_wallet::{
+    bitcoin::{address::NetworkUnchecked, Address},
+    btc_heritage::utils::bitcoin_network,
+    errors::{Error, Result},
+    Database, DatabaseItem,
+};
+use serde::{Deserialize, Serialize};
+
+/// A named recipient of the address book
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Contact {
+    name: String,
+    address: String,
+    note: Option<String>,
+}
+
+impl DatabaseItem for Contact {
+    fn item_key_prefix() -> &'static str {
+        "contact#"
+    }
+    fn item_default_name_key() -> &'static str {
+        "default_contact_name"
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn rename(&mut self, new_name: String) {
+        self.name = new_name;
+    }
+}
+
+impl crate::display::SerdeDisplay for Contact {}
+
+/// Sub-command for the address book of named recipients.
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum ContactSubcmd {
+    /// Add a contact to the address book
+    Add {
+        /// The name of the contact, used as "@<NAME>" in place of an address
+        #[arg(value_parser = parse_contact_name)]
+        name: String,
+        /// The Bitcoin address of the contact
+        address: Address<NetworkUnchecked>,
+        /// A free-form note about the contact
+        #[arg(long)]
+        note: Option<String>,
+    },
+    /// List the contacts of the address book
+    List,
+    /// Display a contact
+    Show { name: String },
+    /// Remove a contact from the address book
+    #[command(visible_aliases = ["delete", "del"])]
+    Remove { name: String },
+}
+
+impl super::CommandExecutor for ContactSubcmd {
+    async fn execute(
+        self,
+        params: Box<dyn Any + Send>,
+    ) -> Result<Box<dyn crate::display::Displayable>> {
+        let mut db: Database = *params.downcast().unwrap();
+
+        let res: Box<dyn crate::display::Displayable> = match self {
+            ContactSubcmd::Add {
+                name,
+                address,
+                note,
+            } => {
+                Contact::verify_name_is_free(&db, &name)?;
+                let address = address
+                    .require_network(bitcoin_network::get())
+                    .map_err(|e| Error::InvalidAddressNetwork(e.to_string()))?;
+                Contact {
+                    name,
+                    address: address.to_string(),
+                    note,
+                }
+                .create(&mut db)?;
+                Box::new("Contact added")
+            }
+            ContactSubcmd::List => Box::new(Contact::all_in_db(&db)?),
+            ContactSubcmd::Show { name } => Box::new(load_contact(&db, &name)?),
+            ContactSubcmd::Remove { name } => {
+                load_contact(&db, &name)?.delete(&mut db)?;
+                Box::new("Contact deleted")
+            }
+        };
+        Ok(res)
+    }
+}
+
+fn load_contact(db: &Database, name: &str) -> Result<Contact> {
+    if !Contact::list_names(db)?.iter().any(|n| n == name) {
+        return Err(Error::Generic(format!("Unknown contact @{name}")));
+    }
+    Contact::load(db, name)
+}
+
+/// The addresses of the address book and the name of their contact
+pub(super) fn get_contacts(db: &Database) -> Result<HashMap<String, String>> {
+    Ok(Contact::all_in_db(db)?
+        .into_iter()
+        .map(|contact| (contact.address, contact.name))
+        .collect())
+}
+
+fn parse_contact_name(val: &str) -> Result<String> {
+    if val.is_empty() || val.contains([':', '@']) || val.contains(char::is_whitespace) {
+        return Err(Error::Generic(
+            "invalid contact name. Must not be empty nor contain ':', '@' or spaces".to_owned(),
+        ));
+    }
+    Ok(val.to_owned())
+}
+
+/// The address of a recipient, given directly or as "@<NAME>" of a contact
+#[derive(Debug, Clone)]
+pub enum RecipientAddress {
+    Address(Address<NetworkUnchecked>),
+    Contact(String),
+}
+
+impl FromStr for RecipientAddress {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.strip_prefix('@') {
+            Some(name) => Ok(Self::Contact(name.to_owned())),
+            None => Ok(Self::Address(Address::from_str(s).map_err(Error::generic)?)),
+        }
+    }
+}
+
+impl RecipientAddress {
+    /// Get the address, looking up the address book if needed, and check it against the Network
+    pub fn resolve(self, db: &Database) -> Result<Address> {
+        let address = match self {
+            RecipientAddress::Address(address) => address,
+            RecipientAddress::Contact(name) => {
+                let contact = load_contact(db, &name)?;
+                Address::from_str(&contact.address).map_err(Error::generic)?
+            }
+        };
+        address
+            .require_network(bitcoin_network::get())
+            .map_err(|e| Error::InvalidAddressNetwork(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contact_names() {
+        assert_eq!(parse_contact_name("alice").unwrap(), "alice");
+        assert!(parse_contact_name("").is_err());
+        assert!(parse_contact_name("al:ice").is_err());
+        assert!(parse_contact_name("@alice").is_err());
+        assert!(parse_contact_name("alice smith").is_err());
+    }
+
+    #[test]
+    fn recipient_address_or_contact() {
+        assert!(matches!(
+            RecipientAddress::from_str("@alice"),
+            Ok(RecipientAddress::Contact(name)) if name == "alice"
+        ));
+        assert!(matches!(
+            RecipientAddress::from_str(
+                "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+            ),
+            Ok(RecipientAddress::Address(_))
+        ));
+        assert!(RecipientAddress::from_str("not-an-address").is_err());
+    }
+}
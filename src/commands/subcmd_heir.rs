@@ -9,11 +9,14 @@ use btc_heritage_wallet::{
     },
     online_wallet::BlockchainProviderConfig,
     AnyKeyProvider, BoundFingerprint, Database, DatabaseItem, Heir, KeyProvider, Language,
-    LocalKey, Mnemonic,
+    LedgerKey, LocalKey, Mnemonic,
 };
 use clap::builder::{PossibleValuesParser, TypedValueParser};
 
-use crate::utils::{ask_user_confirmation, prompt_user_for_password};
+use crate::{
+    display::Unsuccessful,
+    utils::{ask_user_confirmation, prompt_user_for_password},
+};
 
 use super::{subcmd_service_heir::CliHeirPermission, subcmd_wallet::KeyProviderType};
 
@@ -180,7 +183,9 @@ impl super::CommandExecutor for HeirSubcmd {
                         };
                         AnyKeyProvider::LocalKey(local_key)
                     }
-                    KeyProviderType::Ledger => todo!(),
+                    KeyProviderType::Ledger => {
+                        AnyKeyProvider::Ledger(LedgerKey::new(bitcoin_network::get()).await?)
+                    }
                 };
                 let heir_config = if let Some(heir_config) = heir_config {
                     match kind {
@@ -256,7 +261,7 @@ impl super::CommandExecutor for HeirSubcmd {
                         ))
                         .await?
                         {
-                            return Ok(Box::new("Delete heir cancelled"));
+                            return Ok(Box::new(Unsuccessful::cancelled("Delete heir cancelled")));
                         }
                     }
                     if !ask_user_confirmation(&format!(
@@ -265,7 +270,7 @@ impl super::CommandExecutor for HeirSubcmd {
                     ))
                     .await?
                     {
-                        return Ok(Box::new("Delete heir cancelled"));
+                        return Ok(Box::new(Unsuccessful::cancelled("Delete heir cancelled")));
                     }
                 }
                 heir.delete(&mut db)?;
@@ -282,7 +287,9 @@ impl super::CommandExecutor for HeirSubcmd {
                         ))
                         .await?
                         {
-                            return Ok(Box::new("Delete heir mnemonic seed cancelled"));
+                            return Ok(Box::new(Unsuccessful::cancelled(
+                                "Delete heir mnemonic seed cancelled",
+                            )));
                         }
                     }
                 }
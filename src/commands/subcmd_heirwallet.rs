@@ -1,18 +1,21 @@
-use core::any::Any;
-use std::{collections::HashMap, path::PathBuf};
+use core::{any::Any, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
 
 use btc_heritage_wallet::{
-    bitcoin::{address::NetworkUnchecked, psbt::Psbt, Address},
+    bitcoin::{Amount, OutPoint},
     btc_heritage::{
         utils::{bitcoin_network, timestamp_now},
-        HeritageWalletBackup,
+        BlockInclusionObjective, HeritageWalletBackup,
     },
     errors::{Error, Result},
     heritage_provider::{LocalWallet, ServiceBinding},
     heritage_service_api_client::{Fingerprint, HeritageServiceClient, HeritageServiceConfig},
     online_wallet::BlockchainProviderConfig,
     AnyHeritageProvider, AnyKeyProvider, BoundFingerprint, Database, DatabaseItem, HeirWallet,
-    Heritage, HeritageProvider, KeyProvider, Language, LocalKey, Mnemonic, OnlineWallet,
+    Heritage, HeritageProvider, KeyProvider, Language, LedgerKey, LocalKey, Mnemonic, OnlineWallet,
 };
 use chrono::{DateTime, Utc};
 use clap::builder::{PossibleValuesParser, TypedValueParser};
@@ -20,11 +23,20 @@ use serde::ser::SerializeStruct;
 
 use crate::{
     commands::subcmd_heir::HeirConfigType,
+    display::{interactive_println, Unsuccessful},
+    inheritance_psbt::{FeeOverride, InheritanceSpending, MergedInheritances},
+    psbt_analysis::estimated_vsize,
+    psbt_io::{PsbtInput, PsbtOutput},
     spendflow::SpendFlow,
     utils::{ask_user_confirmation, get_fingerprints, prompt_user_for_password},
 };
 
-use super::subcmd_wallet::KeyProviderType;
+use super::{
+    subcmd_contact::{get_contacts, RecipientAddress},
+    subcmd_wallet::{parse_fee_rate, parse_recipient, KeyProviderType, RecipientAmount},
+    subcmd_wallet_ledger_policy::{register_inheritance_policies, WalletLedgerPolicySubcmd},
+    CommandExecutor,
+};
 
 #[derive(Debug)]
 pub struct Inheritance {
@@ -211,6 +223,12 @@ pub enum HeirWalletSubcmd {
         #[arg(short, long, value_enum, default_value_t=HeirConfigType::Xpub)]
         kind: HeirConfigType,
     },
+    /// Commands managing the Ledger wallet policies (BIP388) needed to spend inheritances with a Ledger device
+    #[command(visible_aliases = ["ledger-policy", "lp"])]
+    LedgerPolicies {
+        #[command(subcommand)]
+        subcmd: WalletLedgerPolicySubcmd,
+    },
     /// Sync the local heritage-provider from the Bitcoin network
     Sync,
     /// Display all currently spendable inheritances and their IDs
@@ -228,11 +246,47 @@ pub enum HeirWalletSubcmd {
     #[command(visible_aliases = ["send-inheritance", "send-heritage", "spend-heritage", "send", "spend", "si"])]
     SpendInheritance {
         /// The inheritance ID to spend
-        #[arg(short, long, value_name = "INHERITANCE_ID", required = true)]
-        id: String,
-        /// A recipient address to which send all Heritages with the ID (see the `list-heritages` command).
-        #[arg(short, long, value_name = "ADDRESS", required = true)]
-        recipient: Address<NetworkUnchecked>,
+        /// {n}Can be specified multiple times to spend several inheritances in a single transaction.
+        #[arg(
+            short,
+            long,
+            value_name = "INHERITANCE_ID",
+            required_unless_present = "all_mature"
+        )]
+        id: Vec<String>,
+        /// Spend every mature inheritance in a single transaction (see the `list-inheritances` command)
+        #[arg(long, default_value_t = false, conflicts_with = "id")]
+        all_mature: bool,
+        /// A recipient address and an amount to send them, or only an address (or <ADDRESS>:all)
+        /// to send the remaining of the Heritages with the ID (see the `list-heritages` command).
+        /// {n}<ADDRESS> can also be @<NAME> to use a contact of the address book (see the "contacts" command)
+        /// {n}<AMOUNT> can be a quantity of BTC e.g. 1.0btc, 100mbtc, 100sat
+        /// {n}Can be specified multiple times, exactly one recipient must receive the remaining.
+        #[arg(short, long, value_name = "ADDRESS>[:<AMOUNT]", required = true, value_parser = parse_inheritance_recipient)]
+        recipient: Vec<(RecipientAddress, Option<Amount>)>,
+        /// Force the given fee rate, in sat/vB, for the transaction fee computation
+        #[arg(long, visible_alias = "fr", value_parser = parse_fee_rate, conflicts_with = "fee_absolute")]
+        fee_rate: Option<f32>,
+        /// Force the given absolute fee for the transaction
+        /// {n}<AMOUNT> is a quantity of BTC 0.5mbtc, 123sat
+        #[arg(
+            long,
+            visible_alias = "fa",
+            value_name = "AMOUNT",
+            conflicts_with = "fee_rate"
+        )]
+        fee_absolute: Option<Amount>,
+        /// Use a fee rate estimated for the transaction to be included in the given number of blocks
+        /// {n}Only for heir-wallets with a local-wallets heritage provider, which must be synchronized
+        /// to estimate the fee rate
+        #[arg(long, visible_alias = "bio", value_name = "BLOCKS", conflicts_with_all = ["fee_rate", "fee_absolute"])]
+        block_inclusion_objective: Option<BlockInclusionObjective>,
+        /// Spend only the given heritage UTXO(s) of the inheritance
+        #[arg(long, value_name = "OUTPOINT")]
+        include: Vec<OutPoint>,
+        /// Do not spend the given heritage UTXO(s) of the inheritance
+        #[arg(long, value_name = "OUTPOINT")]
+        exclude: Vec<OutPoint>,
         /// Immediately sign the PSBT
         #[arg(short, long, default_value_t = false)]
         sign: bool,
@@ -243,12 +297,15 @@ pub enum HeirWalletSubcmd {
         /// /!\ BE VERY CAREFULL with that option /!\.
         #[arg(short = 'y', long, default_value_t = false)]
         skip_confirmation: bool,
+        #[command(flatten, next_help_heading = "PSBT output")]
+        psbt_output: PsbtOutput,
     },
     /// Sign every sign-able inputs of the given Partially Signed Bitcoin Transaction (PSBT)
     #[command(visible_alias = "sign")]
     SignPsbt {
         /// The PSBT to sign
-        psbt: Psbt,
+        #[command(flatten)]
+        psbt: PsbtInput,
         /// Immediately broadcast the PSBT after signing it
         #[arg(short, long, default_value_t = false)]
         broadcast: bool,
@@ -256,12 +313,15 @@ pub enum HeirWalletSubcmd {
         /// /!\ BE VERY CAREFULL with that option /!\.
         #[arg(short = 'y', long, default_value_t = false)]
         skip_confirmation: bool,
+        #[command(flatten, next_help_heading = "PSBT output")]
+        psbt_output: PsbtOutput,
     },
     /// Extract a raw transaction from the given Partially Signed Bitcoin Transaction (PSBT) and broadcast it to the Bitcoin network
     #[command(visible_alias = "broadcast")]
     BroadcastPsbt {
         /// The PSBT to broadcast. Must have every inputs correctly signed for this to work.
-        psbt: Psbt,
+        #[command(flatten)]
+        psbt: PsbtInput,
     },
 }
 
@@ -287,6 +347,12 @@ impl super::CommandExecutor for HeirWalletSubcmd {
             | HeirWalletSubcmd::SpendInheritance { .. }
             | HeirWalletSubcmd::BroadcastPsbt { .. } => true,
             HeirWalletSubcmd::SignPsbt { broadcast, .. } if *broadcast => true,
+            HeirWalletSubcmd::LedgerPolicies { subcmd } => match subcmd {
+                WalletLedgerPolicySubcmd::List | WalletLedgerPolicySubcmd::AutoRegister => true,
+                WalletLedgerPolicySubcmd::ListRegistered
+                | WalletLedgerPolicySubcmd::Register { .. }
+                | WalletLedgerPolicySubcmd::ClearRegistered => false,
+            },
             HeirWalletSubcmd::Rename { .. }
             | HeirWalletSubcmd::Remove { .. }
             | HeirWalletSubcmd::Fingerprint
@@ -300,6 +366,13 @@ impl super::CommandExecutor for HeirWalletSubcmd {
             | HeirWalletSubcmd::HeirConfig { .. }
             | HeirWalletSubcmd::Mnemonic { .. } => true,
             HeirWalletSubcmd::SpendInheritance { sign, .. } if *sign => true,
+            HeirWalletSubcmd::LedgerPolicies { subcmd } => match subcmd {
+                WalletLedgerPolicySubcmd::AutoRegister
+                | WalletLedgerPolicySubcmd::Register { .. } => true,
+                WalletLedgerPolicySubcmd::ListRegistered
+                | WalletLedgerPolicySubcmd::List
+                | WalletLedgerPolicySubcmd::ClearRegistered => false,
+            },
             HeirWalletSubcmd::Rename { .. }
             | HeirWalletSubcmd::Sync
             | HeirWalletSubcmd::SpendInheritance { .. }
@@ -310,6 +383,11 @@ impl super::CommandExecutor for HeirWalletSubcmd {
         };
         let need_blockchain_provider = match &self {
             HeirWalletSubcmd::SpendInheritance { broadcast, .. } if *broadcast => true,
+            // Estimating the fee rate requires a synchronization
+            HeirWalletSubcmd::SpendInheritance {
+                block_inclusion_objective,
+                ..
+            } if block_inclusion_objective.is_some() => true,
             HeirWalletSubcmd::SignPsbt { broadcast, .. } if *broadcast => true,
             HeirWalletSubcmd::BroadcastPsbt { .. } | HeirWalletSubcmd::Sync => true,
             _ => false,
@@ -351,7 +429,9 @@ impl super::CommandExecutor for HeirWalletSubcmd {
                         };
                         AnyKeyProvider::LocalKey(local_key)
                     }
-                    KeyProviderType::Ledger => todo!(),
+                    KeyProviderType::Ledger => {
+                        AnyKeyProvider::Ledger(LedgerKey::new(bitcoin_network::get()).await?)
+                    }
                 };
                 let fingerprint = if !key_provider.is_none() {
                     key_provider.fingerprint()?
@@ -448,19 +528,24 @@ impl super::CommandExecutor for HeirWalletSubcmd {
                         ))
                         .await?
                         {
-                            return Ok(Box::new("Delete heir-wallet cancelled"));
+                            return Ok(Box::new(Unsuccessful::cancelled(
+                                "Delete heir-wallet cancelled",
+                            )));
                         }
                     }
                     if !ask_user_confirmation(&format!(
                         "FINAL CONFIRMATION. Are you SURE you want to delete the heir-wallet \"{}\"?",
                         heir.name()
                     )).await?{
-                        return Ok(Box::new("Delete heir-wallet cancelled"));
+                        return Ok(Box::new(Unsuccessful::cancelled("Delete heir-wallet cancelled")));
                     }
                 }
                 heir.delete(&mut db)?;
                 Box::new("Heir wallet deleted")
             }
+            HeirWalletSubcmd::LedgerPolicies { subcmd } => {
+                subcmd.execute(Box::new((heir, db))).await?
+            }
             HeirWalletSubcmd::Fingerprint => Box::new(heir.fingerprint()?),
             HeirWalletSubcmd::Mnemonic => Box::new(heir.backup_mnemonic().await?),
             HeirWalletSubcmd::HeirConfig { kind } => Box::new(
@@ -536,20 +621,160 @@ impl super::CommandExecutor for HeirWalletSubcmd {
             }
             HeirWalletSubcmd::SpendInheritance {
                 id,
+                all_mature,
                 recipient,
+                fee_rate,
+                fee_absolute,
+                block_inclusion_objective,
+                include,
+                exclude,
                 sign,
                 broadcast,
                 skip_confirmation,
+                psbt_output,
             } => {
+                // Resolve the contacts and check every addresses against the Network
                 let recipient = recipient
-                    .require_network(bitcoin_network::get())
-                    .map_err(|e| Error::InvalidAddressNetwork(e.to_string()))?;
+                    .into_iter()
+                    .map(|(ad, am)| Ok((ad.resolve(&db)?, am)))
+                    .collect::<Result<Vec<_>>>()?;
+                let (drain_to, recipients): (Vec<_>, Vec<_>) =
+                    recipient.into_iter().partition(|(_, am)| am.is_none());
+                if drain_to.len() != 1 {
+                    return Err(Error::generic(
+                        "Exactly one recipient must receive the remaining of the inheritance",
+                    ));
+                }
+                let drain_to = drain_to.into_iter().next().expect("verified").0;
+                let spending = InheritanceSpending {
+                    recipients: recipients
+                        .into_iter()
+                        .map(|(ad, am)| (ad, am.expect("partitioned")))
+                        .collect(),
+                    // Clap ensures that fee_absolute and fee_rate cannot be specified together
+                    fee: fee_absolute
+                        .map(FeeOverride::Absolute)
+                        .or(fee_rate.map(FeeOverride::Rate)),
+                    include,
+                    exclude,
+                };
+
+                let ids = if all_mature {
+                    let mut ids = vec![];
+                    for heritage in heir.list_heritages().await? {
+                        if heritage
+                            .maturity
+                            .is_some_and(|maturity| maturity <= timestamp_now())
+                            && !ids.contains(&heritage.heritage_id)
+                        {
+                            ids.push(heritage.heritage_id);
+                        }
+                    }
+                    if ids.is_empty() {
+                        return Err(Error::Generic("No mature inheritance to spend".to_owned()));
+                    }
+                    ids
+                } else {
+                    // Deduplicate, preserving the order
+                    let mut ids = id;
+                    let mut seen = HashSet::new();
+                    ids.retain(|id| seen.insert(id.clone()));
+                    ids
+                };
+
+                // The local heritage wallet estimates the fee rate for its block inclusion objective,
+                // temporarily set it to the requested one
+                let mut heir = heir;
+                let bio_override = if let Some(bio) = block_inclusion_objective {
+                    let AnyHeritageProvider::LocalWallet(local_wallet) = heir.heritage_provider()
+                    else {
+                        return Err(Error::IncorrectHeritageProvider("Local"));
+                    };
+                    let previous_bio = local_wallet
+                        .local_heritage_wallet()
+                        .get_wallet_status()
+                        .await?
+                        .block_inclusion_objective;
+                    Some((bio, previous_bio))
+                } else {
+                    None
+                };
 
                 // Get the PSBT
-                let (psbt, summary) = heir.create_psbt(&id, recipient).await?;
-                SpendFlow::new(psbt, bitcoin_network::get())
+                let psbt_result: Result<_> = async {
+                    if let Some((bio, _)) = bio_override {
+                        let AnyHeritageProvider::LocalWallet(local_wallet) =
+                            heir.heritage_provider_mut()
+                        else {
+                            unreachable!("verified when reading the block inclusion objective");
+                        };
+                        let local_heritage_wallet = local_wallet.local_heritage_wallet_mut();
+                        local_heritage_wallet
+                            .set_block_inclusion_objective(bio)
+                            .await?;
+                        log::info!("Synchronizing to estimate the fee rate...");
+                        local_heritage_wallet.sync().await?;
+                    }
+                    Ok(if ids.len() == 1 {
+                        let (mut psbt, summary) = heir.create_psbt(&ids[0], drain_to).await?;
+                        // The summary of the heritage provider only describes its own PSBT
+                        let summary = if spending.is_default() {
+                            Some(summary)
+                        } else {
+                            spending.apply(&mut psbt)?;
+                            None
+                        };
+                        (psbt, summary)
+                    } else {
+                        let mut psbts = Vec::with_capacity(ids.len());
+                        for id in ids {
+                            let (psbt, _) = heir.create_psbt(&id, drain_to.clone()).await?;
+                            psbts.push((id, psbt));
+                        }
+                        let mut merged = MergedInheritances::merge(psbts)?;
+                        if !spending.is_default() {
+                            spending.apply(&mut merged.psbt)?;
+                        }
+                        interactive_println(&merged.breakdown());
+                        (merged.psbt, None)
+                    })
+                }
+                .await;
+                // Restore the block inclusion objective of the wallet whatever happened
+                if let Some((_, previous_bio)) = bio_override {
+                    let AnyHeritageProvider::LocalWallet(local_wallet) =
+                        heir.heritage_provider_mut()
+                    else {
+                        unreachable!("verified when reading the block inclusion objective");
+                    };
+                    let restored = local_wallet
+                        .local_heritage_wallet_mut()
+                        .set_block_inclusion_objective(previous_bio)
+                        .await;
+                    match (restored, &psbt_result) {
+                        (Err(e), Ok(_)) => return Err(e),
+                        (Err(e), Err(_)) => log::error!(
+                            "Could not restore the block inclusion objective {previous_bio:?}: {e}"
+                        ),
+                        (Ok(_), _) => (),
+                    }
+                }
+                let (psbt, summary) = psbt_result?;
+                if sign {
+                    register_inheritance_policies(&mut heir, &mut db, &psbt).await?;
+                }
+                let fee = psbt.fee().map_err(Error::generic)?.to_sat();
+                interactive_println(&format!(
+                    "\x1b[1mFee\x1b[0m: {fee} sat (~{:.1} sat/vB)",
+                    fee as f64 / estimated_vsize(&psbt) as f64
+                ));
+                let mut spend_flow = SpendFlow::new(psbt, bitcoin_network::get());
+                if let Some(summary) = &summary {
+                    spend_flow = spend_flow.transaction_summary(summary);
+                }
+                spend_flow
                     .fingerprints(&get_fingerprints(&db).await?)
-                    .transaction_summary(&summary)
+                    .contacts(&get_contacts(&db)?)
                     .display()
                     .set_skip_confirmations(skip_confirmation)
                     .set_sign(if sign {
@@ -562,6 +787,7 @@ impl super::CommandExecutor for HeirWalletSubcmd {
                     } else {
                         None
                     })
+                    .psbt_output(&psbt_output)
                     .run()
                     .await?
             }
@@ -569,9 +795,14 @@ impl super::CommandExecutor for HeirWalletSubcmd {
                 psbt,
                 broadcast,
                 skip_confirmation,
+                psbt_output,
             } => {
+                let psbt = psbt.load()?;
+                let mut heir = heir;
+                register_inheritance_policies(&mut heir, &mut db, &psbt).await?;
                 SpendFlow::new(psbt, bitcoin_network::get())
                     .fingerprints(&get_fingerprints(&db).await?)
+                    .contacts(&get_contacts(&db)?)
                     .sign(heir.key_provider())
                     .set_skip_confirmations(skip_confirmation)
                     .set_broadcast(if broadcast {
@@ -579,11 +810,12 @@ impl super::CommandExecutor for HeirWalletSubcmd {
                     } else {
                         None
                     })
+                    .psbt_output(&psbt_output)
                     .run()
                     .await?
             }
             HeirWalletSubcmd::BroadcastPsbt { psbt } => {
-                SpendFlow::<AnyKeyProvider, _>::new(psbt, bitcoin_network::get())
+                SpendFlow::<AnyKeyProvider, _>::new(psbt.load()?, bitcoin_network::get())
                     .broadcast(heir.heritage_provider())
                     .run()
                     .await?
@@ -592,3 +824,20 @@ impl super::CommandExecutor for HeirWalletSubcmd {
         Ok(res)
     }
 }
+
+/// Parse an inheritance recipient, either <ADDRESS>:<AMOUNT> or a plain <ADDRESS>
+/// receiving the remaining of the inheritance
+fn parse_inheritance_recipient(val: &str) -> Result<(RecipientAddress, Option<Amount>)> {
+    if val.contains(':') {
+        let (address, amount) = parse_recipient(val)?;
+        match amount {
+            RecipientAmount::Fixed(amount) => Ok((address, Some(amount))),
+            RecipientAmount::All => Ok((address, None)),
+            RecipientAmount::Percent(_) | RecipientAmount::FeeIncluded(_) => Err(Error::Generic(
+                "invalid recipient. <AMOUNT> must be a quantity of BTC or 'all'".to_owned(),
+            )),
+        }
+    } else {
+        Ok((RecipientAddress::from_str(val)?, None))
+    }
+}
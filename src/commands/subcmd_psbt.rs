@@ -0,0 +1,153 @@
+use core::any::Any;
+
+use btc_heritage_wallet::{
+    bitcoin::{
+        consensus::encode::serialize_hex,
+        psbt::{Input, Psbt},
+        taproot::TapLeafHash,
+        Witness,
+    },
+    btc_heritage::utils::bitcoin_network,
+    errors::{Error, Result},
+    Database, PsbtSummary,
+};
+
+use crate::{
+    display::interactive_println,
+    psbt_io::{PsbtInput, PsbtOutput, PsbtsInput},
+    utils::get_fingerprints,
+};
+
+/// Sub-command for PSBT manipulations, mainly for multi-party signing workflows.
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum PsbtSubcmd {
+    /// Merge the signatures of multiple PSBTs of the same unsigned transaction into one PSBT
+    Combine {
+        /// The PSBTs to combine
+        #[command(flatten)]
+        psbts: PsbtsInput,
+        #[command(flatten)]
+        psbt_output: PsbtOutput,
+    },
+    /// Finalize the inputs of a fully signed PSBT, making it ready for extraction
+    Finalize {
+        /// The PSBT to finalize
+        #[command(flatten)]
+        psbt: PsbtInput,
+        #[command(flatten)]
+        psbt_output: PsbtOutput,
+    },
+    /// Extract the raw transaction, hex encoded, from a fully signed PSBT
+    /// {n}Inputs that are not already finalized are finalized first.
+    Extract {
+        /// The PSBT to extract the transaction from
+        #[command(flatten)]
+        psbt: PsbtInput,
+    },
+}
+
+impl super::CommandExecutor for PsbtSubcmd {
+    async fn execute(
+        self,
+        params: Box<dyn Any + Send>,
+    ) -> Result<Box<dyn crate::display::Displayable>> {
+        let db: Database = *params.downcast().unwrap();
+
+        let res: Box<dyn crate::display::Displayable> = match self {
+            PsbtSubcmd::Combine { psbts, psbt_output } => {
+                let mut psbts = psbts.load()?.into_iter();
+                let mut psbt = psbts.next().expect("clap ensures at least one PSBT");
+                let txid = psbt.unsigned_tx.txid();
+                for (i, other) in psbts.enumerate() {
+                    // Provide a meaningful error before Psbt::combine does
+                    if other.unsigned_tx.txid() != txid {
+                        return Err(Error::Generic(format!(
+                            "PSBT #{} is not for the same unsigned transaction as PSBT #1 \
+                            ({} != {txid})",
+                            i + 2,
+                            other.unsigned_tx.txid()
+                        )));
+                    }
+                    psbt.combine(other).map_err(|e| {
+                        log::error!("Could not combine the PSBTs: {e}");
+                        Error::generic(e)
+                    })?;
+                }
+                display_summary(&psbt, &db).await?;
+                psbt_output.output(&psbt)?
+            }
+            PsbtSubcmd::Finalize { psbt, psbt_output } => {
+                let mut psbt = psbt.load()?;
+                finalize_psbt(&mut psbt)?;
+                display_summary(&psbt, &db).await?;
+                psbt_output.output(&psbt)?
+            }
+            PsbtSubcmd::Extract { psbt } => {
+                let mut psbt = psbt.load()?;
+                finalize_psbt(&mut psbt)?;
+                display_summary(&psbt, &db).await?;
+                Box::new(serialize_hex(&psbt.extract_tx()))
+            }
+        };
+        Ok(res)
+    }
+}
+
+async fn display_summary(psbt: &Psbt, db: &Database) -> Result<()> {
+    let summary =
+        PsbtSummary::try_from((psbt, &get_fingerprints(db).await?, bitcoin_network::get()))?;
+    interactive_println(&summary.to_string());
+    Ok(())
+}
+
+/// Finalize every input of the PSBT that is not already finalized
+///
+/// Heritage wallets only use Taproot outputs, spent either by the key path
+/// or by an Heir script path, which always has a single signature.
+fn finalize_psbt(psbt: &mut Psbt) -> Result<()> {
+    for (i, input) in psbt.inputs.iter_mut().enumerate() {
+        if input.final_script_witness.is_some() || input.final_script_sig.is_some() {
+            continue;
+        }
+        let witness = taproot_witness(input).ok_or_else(|| {
+            Error::Generic(format!(
+                "Input #{} cannot be finalized, it is missing a signature",
+                i + 1
+            ))
+        })?;
+        // Clear everything but the UTXO, as mandated by BIP174
+        *input = Input {
+            witness_utxo: input.witness_utxo.take(),
+            non_witness_utxo: input.non_witness_utxo.take(),
+            final_script_witness: Some(witness),
+            unknown: core::mem::take(&mut input.unknown),
+            proprietary: core::mem::take(&mut input.proprietary),
+            ..Default::default()
+        };
+    }
+    Ok(())
+}
+
+/// Build the final witness of a Taproot input, if it has the necessary signature
+fn taproot_witness(input: &Input) -> Option<Witness> {
+    if let Some(sig) = &input.tap_key_sig {
+        return Some(Witness::from_slice(&[sig.to_vec()]));
+    }
+    input
+        .tap_scripts
+        .iter()
+        .find_map(|(control_block, (script, leaf_version))| {
+            let leaf_hash = TapLeafHash::from_script(script, *leaf_version);
+            input
+                .tap_script_sigs
+                .iter()
+                .find(|((_, lh), _)| *lh == leaf_hash)
+                .map(|(_, sig)| {
+                    Witness::from_slice(&[
+                        sig.to_vec(),
+                        script.to_bytes(),
+                        control_block.serialize(),
+                    ])
+                })
+        })
+}
@@ -6,6 +6,8 @@ use btc_heritage_wallet::{
     Database, DatabaseSingleItem,
 };
 
+use crate::display::interactive_println;
+
 /// Commands related purely to the Heritage service
 #[derive(Debug, Clone, clap::Subcommand)]
 pub enum ServiceSubcmd {
@@ -76,10 +78,14 @@ impl super::CommandExecutor for ServiceSubcmd {
                             &device_auth_response.user_code[4..]
                         );
 
-                        println!("Go to {verification_uri_complete} to approve the connection");
-                        println!();
-                        println!("Verify that the code displayed is: {human_formated_code}");
-                        println!();
+                        interactive_println(&format!(
+                            "Go to {verification_uri_complete} to approve the connection"
+                        ));
+                        interactive_println("");
+                        interactive_println(&format!(
+                            "Verify that the code displayed is: {human_formated_code}"
+                        ));
+                        interactive_println("");
 
                         _ = open::that(verification_uri_complete);
 
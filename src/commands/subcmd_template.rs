@@ -0,0 +1,194 @@
+use core::any::Any;
+
+use btc_heritage_wallet::{
+    btc_heritage::{heritage_config::v1::Days, HeirConfig},
+    errors::{Error, Result},
+    heritage_service_api_client::HeritageServiceConfig,
+    online_wallet::BlockchainProviderConfig,
+    Database, DatabaseItem, Heir,
+};
+use serde::{Deserialize, Serialize};
+
+use super::subcmd_wallet_heritage_config::{parse_days, parse_heir, parse_heir_config, LockSpec};
+
+/// An Heir of an [HeritageConfigTemplate] and its lock
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateHeir<T> {
+    pub heir: T,
+    pub time_lock: LockSpec,
+}
+
+/// The shape of an Heritage Configuration, with Heirs referenced by name, that can be applied
+/// to any wallet (see the "wallet heritage-config set --template" command)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeritageConfigTemplate {
+    name: String,
+    pub minimum_lock_time: Option<Days>,
+    pub local_heirs: Vec<TemplateHeir<String>>,
+    pub service_heirs: Vec<TemplateHeir<String>>,
+    pub heir_configs: Vec<TemplateHeir<HeirConfig>>,
+}
+
+impl DatabaseItem for HeritageConfigTemplate {
+    fn item_key_prefix() -> &'static str {
+        "heritage_config_template#"
+    }
+    fn item_default_name_key() -> &'static str {
+        "default_heritage_config_template_name"
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn rename(&mut self, new_name: String) {
+        self.name = new_name;
+    }
+}
+
+impl crate::display::SerdeDisplay for HeritageConfigTemplate {}
+
+/// Sub-command for Heritage Configuration templates.
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum TemplateSubcmd {
+    /// Create a new template from Heirs references and lock durations
+    Create {
+        /// The minimum lock time, in days, of the Heritage Configurations created from this template.
+        /// {n}If not specified, the default of the "wallet heritage-config set" command applies
+        #[arg(short, long, value_name="DAYS", value_parser=parse_days)]
+        minimum_lock_time: Option<Days>,
+        /// An Heir, consisting of an Heir and a lock duration.
+        /// {n}<LOCAL_HEIR> is a reference to a locally declared Heir (see the "heir" sub-command)
+        /// {n}<LOCK> is the duration from the reference date before this Heir can access the funds,
+        /// in days (720 or 720d), weeks (6w), months (18m) or years (2y)
+        /// {n}Can be specified multiple times.
+        #[arg(long, visible_alias="lh", value_name="LOCAL_HEIR>:<LOCK", value_parser=parse_template_heir)]
+        local_heir: Vec<(String, LockSpec)>,
+        /// An Heir, consisting of an Heir and a lock duration.
+        /// {n}<SERVICE_HEIR> is a reference to a service declared Heir (see the "service heir" sub-command)
+        /// {n}<LOCK> is the duration from the reference date before this Heir can access the funds,
+        /// in days (720 or 720d), weeks (6w), months (18m) or years (2y)
+        /// {n}Can be specified multiple times.
+        #[arg(long, visible_alias="sh", value_name="SERVICE_HEIR>:<LOCK", value_parser=parse_template_heir)]
+        service_heir: Vec<(String, LockSpec)>,
+        /// An HeirConfig and a lock duration.
+        /// {n}<KIND> is the kind of Heir Config (see the "heir" sub-command), can be ommited, defaults to xpub
+        /// {n}<VALUE> is an appropriate string for the `KIND` of Heir Config
+        /// {n}<LOCK> is the duration from the reference date before this Heir can access the funds,
+        /// in days (720 or 720d), weeks (6w), months (18m) or years (2y)
+        /// {n}Can be specified multiple times.
+        #[arg(long, visible_alias="hc", value_name="KIND>:<VALUE>:<LOCK", value_parser=parse_template_heir_config)]
+        heir_config: Vec<(HeirConfig, LockSpec)>,
+    },
+    /// Display the template
+    Show,
+    /// Rename the template in the database to a new name
+    Rename { new_name: String },
+    /// Remove the template from the database
+    #[command(visible_aliases = ["delete", "del"])]
+    Remove,
+}
+
+/// Templates are applied with different reference dates, so their locks must be durations
+fn ensure_duration(lock: &LockSpec) -> core::result::Result<(), String> {
+    match lock {
+        LockSpec::Date(date) => Err(format!(
+            "A template only accepts lock durations, not an absolute date ({date})"
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn parse_template_heir(val: &str) -> core::result::Result<(String, LockSpec), String> {
+    let (heir, lock) = parse_heir(val)?;
+    ensure_duration(&lock)?;
+    Ok((heir, lock))
+}
+
+fn parse_template_heir_config(val: &str) -> core::result::Result<(HeirConfig, LockSpec), String> {
+    let (heir_config, lock) = parse_heir_config(val)?;
+    ensure_duration(&lock)?;
+    Ok((heir_config, lock))
+}
+
+impl super::CommandExecutor for TemplateSubcmd {
+    async fn execute(
+        self,
+        params: Box<dyn Any + Send>,
+    ) -> Result<Box<dyn crate::display::Displayable>> {
+        let (mut db, template_name, _hsc, _bcpc): (
+            Database,
+            String,
+            HeritageServiceConfig,
+            BlockchainProviderConfig,
+        ) = *params.downcast().unwrap();
+
+        let res: Box<dyn crate::display::Displayable> = match self {
+            TemplateSubcmd::Create {
+                minimum_lock_time,
+                local_heir,
+                service_heir,
+                heir_config,
+            } => {
+                HeritageConfigTemplate::verify_name_is_free(&db, &template_name)?;
+                if local_heir.is_empty() && service_heir.is_empty() && heir_config.is_empty() {
+                    return Err(Error::Generic(
+                        "A template must have at least one Heir".to_owned(),
+                    ));
+                }
+                // Local Heirs can be verified now, service Heirs will be when the template is used
+                let local_heir_names = Heir::list_names(&db)?;
+                if let Some((unknown, _)) = local_heir
+                    .iter()
+                    .find(|(name, _)| !local_heir_names.contains(name))
+                {
+                    return Err(Error::Generic(format!("{unknown} does not exist")));
+                }
+                let template = HeritageConfigTemplate {
+                    name: template_name,
+                    minimum_lock_time,
+                    local_heirs: local_heir
+                        .into_iter()
+                        .map(|(heir, time_lock)| TemplateHeir { heir, time_lock })
+                        .collect(),
+                    service_heirs: service_heir
+                        .into_iter()
+                        .map(|(heir, time_lock)| TemplateHeir { heir, time_lock })
+                        .collect(),
+                    heir_configs: heir_config
+                        .into_iter()
+                        .map(|(heir, time_lock)| TemplateHeir { heir, time_lock })
+                        .collect(),
+                };
+                template.create(&mut db)?;
+                Box::new("Template created")
+            }
+            TemplateSubcmd::Show => Box::new(HeritageConfigTemplate::load(&db, &template_name)?),
+            TemplateSubcmd::Rename { new_name } => {
+                // First verify the destination name is free
+                HeritageConfigTemplate::verify_name_is_free(&db, &new_name)?;
+                // Rename
+                let mut template = HeritageConfigTemplate::load(&db, &template_name)?;
+                template.db_rename(&mut db, new_name)?;
+                Box::new("Template renamed")
+            }
+            TemplateSubcmd::Remove => {
+                HeritageConfigTemplate::load(&db, &template_name)?.delete(&mut db)?;
+                Box::new("Template deleted")
+            }
+        };
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_heir_locks_are_durations() {
+        assert_eq!(
+            parse_template_heir("alice:2y"),
+            Ok(("alice".to_owned(), LockSpec::Years(2)))
+        );
+        assert!(parse_template_heir("alice@2030-06-01").is_err());
+    }
+}
@@ -1,30 +1,55 @@
 use core::{any::Any, str::FromStr};
-use std::{collections::HashSet, io::Write, path::PathBuf};
+use std::{
+    collections::HashSet,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
 use btc_heritage_wallet::{
-    bitcoin::{
-        address::NetworkUnchecked, bip32::Fingerprint, psbt::Psbt, Address, Amount, OutPoint,
+    bitcoin::{bip32::Fingerprint, psbt::Psbt, Amount, OutPoint, Txid},
+    btc_heritage::{
+        heritage_wallet::{TransactionSummary, TransactionSummaryOwnedIO},
+        utils::{bitcoin_network, timestamp_now},
+        BlockInclusionObjective, HeritageWalletBackup,
     },
-    btc_heritage::{utils::bitcoin_network, BlockInclusionObjective, HeritageWalletBackup},
     errors::{Error, Result},
     heritage_service_api_client::{
-        HeritageServiceClient, HeritageServiceConfig, NewTx, NewTxDrainTo, NewTxFeePolicy,
-        NewTxRecipient, NewTxSpendingConfig, NewTxUtxoSelection,
+        AccountXPubWithStatus, HeritageServiceClient, HeritageServiceConfig, NewTx, NewTxDrainTo,
+        NewTxFeePolicy, NewTxRecipient, NewTxSpendingConfig, NewTxUtxoSelection,
+        SubwalletConfigMeta,
     },
-    online_wallet::{BlockchainProviderConfig, LocalHeritageWallet, ServiceBinding},
+    online_wallet::{BlockchainProviderConfig, LocalHeritageWallet, ServiceBinding, WalletStatus},
     AnyKeyProvider, AnyOnlineWallet, BoundFingerprint, Database, DatabaseItem, KeyProvider,
     Language, LedgerKey, LocalKey, Mnemonic, OnlineWallet, Wallet,
 };
+use chrono::DateTime;
 use clap::builder::{PossibleValuesParser, TypedValueParser};
 
 use crate::{
     commands::{subcmd_heir::HeirConfigType, subcmd_service_wallet},
+    display::{interactive_println, Unsuccessful},
+    inheritance_psbt::DUST_LIMIT,
+    psbt_analysis::estimated_vsize,
+    psbt_io::{PsbtInput, PsbtOutput},
     spendflow::SpendFlow,
-    utils::{ask_user_confirmation, get_fingerprints, prompt_user_for_password},
+    utils::{
+        ask_user_confirmation, current_fee_rate, get_fingerprints, prompt_user_for_password,
+        wallet_address_to_string,
+    },
 };
 
 use super::{
-    subcmd_wallet_axpubs::WalletAXpubSubcmd, subcmd_wallet_ledger_policy::WalletLedgerPolicySubcmd,
+    subcmd_contact::{get_contacts, RecipientAddress},
+    subcmd_wallet_addresses::{verify_on_ledger, WalletAddressSubcmd},
+    subcmd_wallet_axpubs::WalletAXpubSubcmd,
+    subcmd_wallet_heritage_config::{HeritageConfigV1View, WalletHeritageConfigSubcmd},
+    subcmd_wallet_labels::{
+        get_labels, LabeledAddresses, LabeledTransaction, LabeledUtxo, WalletLabelSubcmd,
+        WalletLabels,
+    },
+    subcmd_wallet_ledger_policy::WalletLedgerPolicySubcmd,
+    subcmd_wallet_transactions::{TransactionRecord, WalletTransactionSubcmd},
+    subcmd_wallet_utxos::WalletUtxoSubcmd,
 };
 
 /// Sub-command for wallets.
@@ -130,13 +155,37 @@ pub enum WalletSubcmd {
     #[command(visible_aliases = ["delete", "del"])]
     Remove,
     /// Get a new address for this wallet, based on the current Heritage Configuration
-    NewAddress,
-    /// List all the existing addresses for this wallet
-    Addresses,
-    /// List all the past transactions for this wallet
-    Transactions,
-    /// List all the UTXOs of this wallet
-    Utxos,
+    NewAddress {
+        /// Display the address on the Ledger device to confirm on its screen that it belongs to the wallet.
+        /// {n}The Ledger policy of the current Heritage Configuration must be registered (see the "ledger-policies" command)
+        #[arg(long, default_value_t = false)]
+        verify: bool,
+    },
+    /// List all the existing addresses for this wallet, or verify one on the Ledger device
+    #[command(visible_alias = "address")]
+    Addresses {
+        #[command(subcommand)]
+        subcmd: Option<WalletAddressSubcmd>,
+    },
+    /// List all the past transactions for this wallet, or export them
+    #[command(visible_alias = "transaction")]
+    Transactions {
+        #[command(subcommand)]
+        subcmd: Option<WalletTransactionSubcmd>,
+    },
+    /// List all the UTXOs of this wallet, or manage their labels and frozen state
+    #[command(visible_alias = "utxo")]
+    Utxos {
+        #[command(subcommand)]
+        subcmd: Option<WalletUtxoSubcmd>,
+    },
+    /// Commands managing the labels of the transactions, addresses and UTXOs of the wallet,
+    /// with BIP329 import and export to share them with other wallets
+    #[command(visible_alias = "label")]
+    Labels {
+        #[command(subcommand)]
+        subcmd: WalletLabelSubcmd,
+    },
     /// Commands managing the Ledger wallet policies (BIP388) of the wallet
     #[command(visible_aliases = ["ledger-policy", "lp"])]
     LedgerPolicies {
@@ -147,7 +196,7 @@ pub enum WalletSubcmd {
     #[command(visible_aliases = ["heritage-config", "hc"])]
     HeritageConfigs {
         #[command(subcommand)]
-        subcmd: super::subcmd_wallet_heritage_config::WalletHeritageConfigSubcmd,
+        subcmd: WalletHeritageConfigSubcmd,
     },
     /// Commands managing the Account eXtended Public Keys of the wallet
     #[command(visible_aliases = ["account-xpub", "ax"])]
@@ -158,8 +207,11 @@ pub enum WalletSubcmd {
     /// Sync the wallet from the Bitcoin network, updating the balance and the fee rate as needed
     Sync,
     /// Display the balance of the wallet
-    #[command(visible_aliases = ["status", "stat"])]
     Balance,
+    /// Display a dashboard of the wallet: balance, current Heritage Configuration, unused
+    /// Account eXtended Public Keys, last synchronization and the first Heir maturity date
+    #[command(visible_alias = "stat")]
+    Status,
     /// Display the current Block Inclusion Objective (bio) of the wallet. It is used to compute the fee when creating a new transaction.
     #[command(visible_alias = "bio")]
     BlockInclusionObjective {
@@ -187,10 +239,19 @@ pub enum WalletSubcmd {
     #[command(visible_aliases = ["send-bitcoin", "spend-bitcoins", "spend-bitcoin", "sb"])]
     SendBitcoins {
         /// A recipient address and an amount to send them.
+        /// {n}<ADDRESS> can also be @<NAME> to use a contact of the address book (see the "contacts" command)
         /// {n}<AMOUNT> can be a quantity of BTC e.g. 1.0btc, 100mbtc, 100sat
+        /// {n}or a percentage of the spendable balance of the wallet, frozen UTXOs excluded, e.g. 50%
+        /// {n}or a quantity of BTC suffixed by '-fee' to deduct the fee from it e.g. 0.1btc-fee
         /// {n}or 'all' to drain the wallet
-        #[arg(short, long, value_name="ADDRESS>:<AMOUNT", required = true, value_parser=parse_recipient)]
-        recipient: Vec<(Address<NetworkUnchecked>, Option<Amount>)>,
+        #[arg(short, long, value_name="ADDRESS>:<AMOUNT", required_unless_present = "recipients_file", value_parser=parse_recipient)]
+        recipient: Vec<(RecipientAddress, RecipientAmount)>,
+        /// A file listing recipients for a batch payment, in addition to the --recipient ones.
+        /// {n}If the extension is .json, a JSON array of {"address": <ADDRESS>, "amount": <AMOUNT>, "label": <LABEL>}
+        /// {n}Else a CSV file with one <ADDRESS>,<AMOUNT>[,<LABEL>] line per recipient, the header line being optional
+        /// {n}<ADDRESS> and <AMOUNT> are the same as for --recipient, <LABEL> is optional
+        #[arg(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+        recipients_file: Option<PathBuf>,
         /// Force the given fee rate, in sat/vB, for the transaction fee computation
         #[arg(long, visible_alias = "fr", value_parser = parse_fee_rate, conflicts_with = "fee_absolute")]
         fee_rate: Option<f32>,
@@ -206,7 +267,8 @@ pub enum WalletSubcmd {
         /// Force the spending of the given UTXO(s) in the transaction
         #[arg(long, value_name = "OUTPOINT")]
         include: Vec<OutPoint>,
-        /// Forbid the spending of the given UTXO(s) in the transaction
+        /// Forbid the spending of the given UTXO(s) in the transaction.
+        /// {n}The frozen UTXOs (see the "utxos freeze" command) are always excluded, unless included
         #[arg(long, value_name = "OUTPOINT")]
         exclude: Vec<OutPoint>,
         /// Spend only the UTXO(s) of the "include" list
@@ -236,12 +298,80 @@ pub enum WalletSubcmd {
         /// /!\ BE VERY CAREFULL with that option /!\.
         #[arg(short = 'y', long, default_value_t = false)]
         skip_confirmation: bool,
+        #[command(flatten, next_help_heading = "PSBT output")]
+        psbt_output: PsbtOutput,
+    },
+    /// Replace an unconfirmed transaction of the wallet with one paying a higher fee (Replace-By-Fee)
+    /// {n}The replacement spends the same inputs and pays the same recipients, more inputs are added if needed.
+    /// {n}The fee must also pay 1 sat/vB for the replacement itself (BIP125), it is raised if needed.
+    BumpFee {
+        /// The ID of the transaction to replace. It must have been signed by this wallet
+        txid: Txid,
+        /// The fee rate, in sat/vB, of the replacement transaction. Must be higher than the one of the original transaction
+        #[arg(long, visible_alias = "fr", value_parser = parse_fee_rate)]
+        fee_rate: f32,
+        /// Immediately sign the PSBT
+        #[arg(short, long, default_value_t = false)]
+        sign: bool,
+        /// Immediately broadcast the PSBT after signing it
+        #[arg(short, long, default_value_t = false, requires = "sign")]
+        broadcast: bool,
+        /// If --sign or --broadcast are requested, do it without asking for confirmation{n}
+        /// /!\ BE VERY CAREFULL with that option /!\.
+        #[arg(short = 'y', long, default_value_t = false)]
+        skip_confirmation: bool,
+        #[command(flatten, next_help_heading = "PSBT output")]
+        psbt_output: PsbtOutput,
+    },
+    /// Accelerate an unconfirmed transaction of the wallet by spending its change in a child transaction
+    /// paying a high fee (Child-Pays-For-Parent)
+    Cpfp {
+        /// The ID of the transaction to accelerate. It must have been signed by this wallet
+        txid: Txid,
+        /// The fee rate, in sat/vB, of the parent and child transactions taken together
+        #[arg(long, visible_alias = "fr", value_parser = parse_fee_rate)]
+        fee_rate: f32,
+        /// Immediately sign the PSBT
+        #[arg(short, long, default_value_t = false)]
+        sign: bool,
+        /// Immediately broadcast the PSBT after signing it
+        #[arg(short, long, default_value_t = false, requires = "sign")]
+        broadcast: bool,
+        /// If --sign or --broadcast are requested, do it without asking for confirmation{n}
+        /// /!\ BE VERY CAREFULL with that option /!\.
+        #[arg(short = 'y', long, default_value_t = false)]
+        skip_confirmation: bool,
+        #[command(flatten, next_help_heading = "PSBT output")]
+        psbt_output: PsbtOutput,
+    },
+    /// Cancel an unconfirmed transaction of the wallet by replacing it with one sending all its inputs
+    /// back to a new address of the wallet, with a higher fee (Replace-By-Fee)
+    CancelTx {
+        /// The ID of the transaction to cancel
+        txid: Txid,
+        /// Force the given fee rate, in sat/vB, for the cancelling transaction.
+        /// {n}The resulting fee must be higher than the one of the original transaction
+        #[arg(long, visible_alias = "fr", value_parser = parse_fee_rate)]
+        fee_rate: Option<f32>,
+        /// Immediately sign the PSBT
+        #[arg(short, long, default_value_t = false)]
+        sign: bool,
+        /// Immediately broadcast the PSBT after signing it
+        #[arg(short, long, default_value_t = false, requires = "sign")]
+        broadcast: bool,
+        /// Do not ask for confirmation, neither for the cancellation nor for --sign or --broadcast{n}
+        /// /!\ BE VERY CAREFULL with that option /!\.
+        #[arg(short = 'y', long, default_value_t = false)]
+        skip_confirmation: bool,
+        #[command(flatten, next_help_heading = "PSBT output")]
+        psbt_output: PsbtOutput,
     },
     /// Sign every sign-able inputs of the given Partially Signed Bitcoin Transaction (PSBT)
     #[command(visible_alias = "sign")]
     SignPsbt {
         /// The PSBT to sign
-        psbt: Psbt,
+        #[command(flatten)]
+        psbt: PsbtInput,
         /// Immediately broadcast the PSBT after signing it
         #[arg(short, long, default_value_t = false)]
         broadcast: bool,
@@ -249,15 +379,145 @@ pub enum WalletSubcmd {
         /// /!\ BE VERY CAREFULL with that option /!\.
         #[arg(short = 'y', long, default_value_t = false)]
         skip_confirmation: bool,
+        #[command(flatten, next_help_heading = "PSBT output")]
+        psbt_output: PsbtOutput,
     },
     /// Extract a raw transaction from the given Partially Signed Bitcoin Transaction (PSBT) and broadcast it to the Bitcoin network
     #[command(visible_alias = "broadcast")]
     BroadcastPsbt {
         /// The PSBT to broadcast. Must have every inputs correctly signed for this to work.
-        psbt: Psbt,
+        #[command(flatten)]
+        psbt: PsbtInput,
     },
 }
 
+/// Below this number of days before the first Heir maturity, the dashboard emits a warning
+const HEIR_MATURITY_WARNING_DAYS: i64 = 90;
+/// Above this number of days since the last synchronization, the dashboard emits a warning
+const SYNC_AGE_WARNING_DAYS: i64 = 7;
+
+#[derive(Debug, serde::Serialize)]
+pub struct WalletDashboard {
+    status: WalletStatus,
+    /// The date of the last synchronization with the blockchain
+    last_sync: String,
+    /// The Heritage Configuration currently used to generate new addresses
+    current_heritage_config: Option<SubwalletConfigMeta>,
+    /// The number of Account eXtended Public Keys available to create new Heritage Configurations
+    unused_account_xpubs: usize,
+    /// The earliest date at which an Heir will be able to spend some of the coins of the wallet
+    first_heir_maturity: Option<String>,
+    warnings: Vec<String>,
+}
+impl crate::display::SerdeDisplay for WalletDashboard {}
+impl WalletDashboard {
+    async fn build(wallet: &Wallet) -> Result<Self> {
+        let status = wallet.get_wallet_status().await?;
+        let mut subwallet_configs = wallet.list_subwallet_configs().await?;
+        let current_heritage_config = if !subwallet_configs.is_empty() {
+            Some(subwallet_configs.remove(0))
+        } else {
+            None
+        };
+        let unused_account_xpubs = wallet
+            .list_account_xpubs()
+            .await?
+            .iter()
+            .filter(|axpub| matches!(axpub, AccountXPubWithStatus::Unused(_)))
+            .count();
+
+        // The coins may still be locked by obsolete Heritage Configurations,
+        // those are the ones that will mature first
+        let mut heritage_configs = vec![];
+        for utxo in wallet.online_wallet().list_heritage_utxos().await? {
+            if !heritage_configs.contains(&utxo.heritage_config) {
+                heritage_configs.push(utxo.heritage_config);
+            }
+        }
+        let holds_coins = !heritage_configs.is_empty();
+        if !holds_coins {
+            if let Some(current) = &current_heritage_config {
+                heritage_configs.push(current.heritage_config.clone());
+            }
+        }
+        let first_heir_maturity_ts = heritage_configs
+            .iter()
+            .map(HeritageConfigV1View::new)
+            .collect::<Result<Vec<_>>>()?
+            .iter()
+            .filter_map(HeritageConfigV1View::first_maturity_ts)
+            .min();
+
+        let now = timestamp_now() as i64;
+        let mut warnings = vec![];
+        if current_heritage_config.is_none() {
+            warnings.push(
+                "no Heritage Configuration, your heirs will not be able \
+                to inherit (see the \"heritage-configs set\" command)"
+                    .to_owned(),
+            );
+        }
+        if unused_account_xpubs == 0 {
+            warnings.push(
+                "no unused Account eXtended Public Key left, you will not be able \
+                to set a new Heritage Configuration (see the \"account-xpubs auto-add\" command)"
+                    .to_owned(),
+            );
+        }
+        // Only warn if there are coins to inherit
+        if let Some(ts) = first_heir_maturity_ts.filter(|_| holds_coins) {
+            let days_left = (ts as i64 - now) / 86400;
+            if ts as i64 <= now {
+                warnings.push(
+                    "the first heir can already spend some of your coins, \
+                    move them to reset the dead-man switch"
+                        .to_owned(),
+                );
+            } else if days_left < HEIR_MATURITY_WARNING_DAYS {
+                warnings.push(format!(
+                    "first heir matures in {days_left} days, \
+                    move coins to reset the dead-man switch"
+                ));
+            }
+        }
+        // A zero timestamp means the wallet never synchronized
+        if status.last_sync_ts == 0 {
+            warnings
+                .push("the wallet was never synchronized (see the \"sync\" command)".to_owned());
+        } else {
+            let sync_age_days = (now - status.last_sync_ts as i64) / 86400;
+            if sync_age_days > SYNC_AGE_WARNING_DAYS {
+                warnings.push(format!(
+                    "last synchronization was {sync_age_days} days ago (see the \"sync\" command)"
+                ));
+            }
+        }
+
+        Ok(WalletDashboard {
+            last_sync: format_last_sync(status.last_sync_ts),
+            status,
+            current_heritage_config,
+            unused_account_xpubs,
+            first_heir_maturity: first_heir_maturity_ts.map(format_ts),
+            warnings,
+        })
+    }
+}
+
+fn format_ts(ts: u64) -> String {
+    DateTime::from_timestamp(ts as i64, 0)
+        .expect("timestamp is in range")
+        .to_string()
+}
+
+fn format_last_sync(ts: u64) -> String {
+    if ts == 0 {
+        "never synced".to_owned()
+    } else {
+        format_ts(ts)
+    }
+}
+
 #[derive(Debug, Clone, Copy, clap::ValueEnum)]
 pub enum OnlineWalletType {
     /// No online wallet, the resulting wallet will not be able to sync, generate addresses, etc... (it will be sign-only)
@@ -298,13 +558,17 @@ impl super::CommandExecutor for WalletSubcmd {
             | WalletSubcmd::Backup { .. }
             | WalletSubcmd::Sync
             | WalletSubcmd::Balance
+            | WalletSubcmd::Status
             | WalletSubcmd::SendBitcoins { .. }
+            | WalletSubcmd::BumpFee { .. }
+            | WalletSubcmd::Cpfp { .. }
+            | WalletSubcmd::CancelTx { .. }
             | WalletSubcmd::BroadcastPsbt { .. }
             | WalletSubcmd::BlockInclusionObjective { .. }
-            | WalletSubcmd::Addresses
-            | WalletSubcmd::Transactions
-            | WalletSubcmd::Utxos
-            | WalletSubcmd::NewAddress
+            | WalletSubcmd::Addresses { .. }
+            | WalletSubcmd::Transactions { .. }
+            | WalletSubcmd::Utxos { .. }
+            | WalletSubcmd::NewAddress { .. }
             | WalletSubcmd::HeritageConfigs { .. } => true,
             WalletSubcmd::SignPsbt { broadcast, .. } if *broadcast => true,
             WalletSubcmd::Rename { local_only, .. } if !*local_only => true,
@@ -325,6 +589,7 @@ impl super::CommandExecutor for WalletSubcmd {
             | WalletSubcmd::Rename { .. }
             | WalletSubcmd::Fingerprint
             | WalletSubcmd::Mnemonic { .. }
+            | WalletSubcmd::Labels { .. }
             | WalletSubcmd::HeirConfig { .. } => false,
         };
         let need_key_provider = match &self {
@@ -343,27 +608,54 @@ impl super::CommandExecutor for WalletSubcmd {
                 WalletAXpubSubcmd::Generate { .. } | WalletAXpubSubcmd::AutoAdd { .. } => true,
                 WalletAXpubSubcmd::ListAdded { .. } | WalletAXpubSubcmd::Add { .. } => false,
             },
-            WalletSubcmd::SendBitcoins { sign, .. } if *sign => true,
+            WalletSubcmd::SendBitcoins { sign, .. }
+            | WalletSubcmd::BumpFee { sign, .. }
+            | WalletSubcmd::Cpfp { sign, .. }
+            | WalletSubcmd::CancelTx { sign, .. }
+                if *sign =>
+            {
+                true
+            }
+            WalletSubcmd::HeritageConfigs {
+                subcmd: WalletHeritageConfigSubcmd::Renew { sign, .. },
+            } if *sign => true,
+            WalletSubcmd::NewAddress { verify } if *verify => true,
+            WalletSubcmd::Addresses { subcmd: Some(_) } => true,
             WalletSubcmd::Rename { .. }
             | WalletSubcmd::SendBitcoins { .. }
+            | WalletSubcmd::BumpFee { .. }
+            | WalletSubcmd::Cpfp { .. }
+            | WalletSubcmd::CancelTx { .. }
             | WalletSubcmd::Backup { .. }
             | WalletSubcmd::Remove
-            | WalletSubcmd::NewAddress
-            | WalletSubcmd::Addresses
-            | WalletSubcmd::Utxos
-            | WalletSubcmd::Transactions
+            | WalletSubcmd::NewAddress { .. }
+            | WalletSubcmd::Addresses { .. }
+            | WalletSubcmd::Utxos { .. }
+            | WalletSubcmd::Transactions { .. }
+            | WalletSubcmd::Labels { .. }
             | WalletSubcmd::HeritageConfigs { .. }
             | WalletSubcmd::Sync
             | WalletSubcmd::Balance
+            | WalletSubcmd::Status
             | WalletSubcmd::BlockInclusionObjective { .. }
             | WalletSubcmd::Fingerprint
             | WalletSubcmd::BroadcastPsbt { .. } => false,
         };
         let need_blockchain_provider = match &self {
             WalletSubcmd::Sync => true,
-            WalletSubcmd::SendBitcoins { broadcast, .. } if *broadcast => true,
+            WalletSubcmd::SendBitcoins { broadcast, .. }
+            | WalletSubcmd::BumpFee { broadcast, .. }
+            | WalletSubcmd::Cpfp { broadcast, .. }
+            | WalletSubcmd::CancelTx { broadcast, .. }
+                if *broadcast =>
+            {
+                true
+            }
             WalletSubcmd::SignPsbt { broadcast, .. } if *broadcast => true,
             WalletSubcmd::BroadcastPsbt { .. } => true,
+            WalletSubcmd::HeritageConfigs {
+                subcmd: WalletHeritageConfigSubcmd::Renew { broadcast, .. },
+            } if *broadcast => true,
             _ => false,
         };
 
@@ -538,8 +830,14 @@ impl super::CommandExecutor for WalletSubcmd {
                         .await?;
                     }
                 };
-                // Rename
-                wallet.db_rename(&mut db, new_name)?;
+                // Rename, along with the labels and the transaction records of the wallet
+                let mut labels = WalletLabels::load_or_default(&db, wallet.name())?;
+                let old_name = wallet.name().to_owned();
+                wallet.db_rename(&mut db, new_name.clone())?;
+                TransactionRecord::rename_all(&mut db, &old_name, &new_name)?;
+                if labels.is_stored(&db)? {
+                    labels.db_rename(&mut db, new_name)?;
+                }
                 Box::new("Wallet renamed")
             }
             WalletSubcmd::Backup {
@@ -570,7 +868,9 @@ impl super::CommandExecutor for WalletSubcmd {
                         ))
                         .await?
                         {
-                            return Ok(Box::new("Delete wallet cancelled"));
+                            return Ok(Box::new(Unsuccessful::cancelled(
+                                "Delete wallet cancelled",
+                            )));
                         }
                     }
                     if !wallet.online_wallet().is_none() {
@@ -580,7 +880,9 @@ impl super::CommandExecutor for WalletSubcmd {
                         ))
                         .await?
                         {
-                            return Ok(Box::new("Delete wallet cancelled"));
+                            return Ok(Box::new(Unsuccessful::cancelled(
+                                "Delete wallet cancelled",
+                            )));
                         }
                     }
                     if !ask_user_confirmation(
@@ -589,7 +891,7 @@ impl super::CommandExecutor for WalletSubcmd {
                     )
                     .await?
                     {
-                        return Ok(Box::new("Delete wallet cancelled"));
+                        return Ok(Box::new(Unsuccessful::cancelled("Delete wallet cancelled")));
                     }
                     if !ask_user_confirmation(&format!(
                         "FINAL CONFIRMATION. Are you 100% SURE you want to \
@@ -597,18 +899,65 @@ impl super::CommandExecutor for WalletSubcmd {
                     ))
                     .await?
                     {
-                        return Ok(Box::new("Delete wallet cancelled"));
+                        return Ok(Box::new(Unsuccessful::cancelled("Delete wallet cancelled")));
                     }
                 }
+                let labels = WalletLabels::load_or_default(&db, wallet.name())?;
+                if labels.is_stored(&db)? {
+                    labels.delete(&mut db)?;
+                }
+                TransactionRecord::delete_all(&mut db, wallet.name())?;
                 wallet.delete(&mut db)?;
                 Box::new("Wallet deleted")
             }
-            WalletSubcmd::NewAddress => Box::new(wallet.online_wallet().get_address().await?),
-            WalletSubcmd::Addresses => Box::new(wallet.online_wallet().list_addresses().await?),
-            WalletSubcmd::Transactions => {
-                Box::new(wallet.online_wallet().list_transactions().await?)
+            WalletSubcmd::NewAddress { verify } => {
+                let wallet_address = wallet.online_wallet().get_address().await?;
+                if verify {
+                    verify_on_ledger(&wallet, &wallet_address).await?;
+                }
+                Box::new(wallet_address)
+            }
+            WalletSubcmd::Addresses {
+                subcmd: Some(subcmd),
+            } => subcmd.execute(Box::new((wallet, db))).await?,
+            WalletSubcmd::Addresses { subcmd: None } => {
+                let labels = WalletLabels::load_or_default(&db, wallet.name())?;
+                Box::new(LabeledAddresses::new(
+                    wallet.online_wallet().list_addresses().await?,
+                    &labels,
+                ))
+            }
+            WalletSubcmd::Transactions { subcmd: None } => {
+                let labels = WalletLabels::load_or_default(&db, wallet.name())?;
+                Box::new(
+                    wallet
+                        .online_wallet()
+                        .list_transactions()
+                        .await?
+                        .into_iter()
+                        .map(|tx| LabeledTransaction::new(tx, &labels))
+                        .collect::<Vec<_>>(),
+                )
             }
-            WalletSubcmd::Utxos => Box::new(wallet.online_wallet().list_heritage_utxos().await?),
+            WalletSubcmd::Transactions {
+                subcmd: Some(subcmd),
+            } => subcmd.execute(Box::new((wallet, db))).await?,
+            WalletSubcmd::Utxos { subcmd: None } => {
+                let labels = WalletLabels::load_or_default(&db, wallet.name())?;
+                Box::new(
+                    wallet
+                        .online_wallet()
+                        .list_heritage_utxos()
+                        .await?
+                        .into_iter()
+                        .map(|utxo| LabeledUtxo::new(utxo, &labels))
+                        .collect::<Vec<_>>(),
+                )
+            }
+            WalletSubcmd::Labels { subcmd } => subcmd.execute(Box::new((wallet, db))).await?,
+            WalletSubcmd::Utxos {
+                subcmd: Some(subcmd),
+            } => subcmd.execute(Box::new((wallet, db))).await?,
             WalletSubcmd::LedgerPolicies { subcmd } => {
                 subcmd.execute(Box::new((wallet, db))).await?
             }
@@ -621,6 +970,7 @@ impl super::CommandExecutor for WalletSubcmd {
                 Box::new("Synchronization done")
             }
             WalletSubcmd::Balance => Box::new(wallet.get_wallet_status().await?),
+            WalletSubcmd::Status => Box::new(WalletDashboard::build(&wallet).await?),
             WalletSubcmd::BlockInclusionObjective { set } => {
                 let wallet_status = if let Some(bio) = set {
                     wallet.set_block_inclusion_objective(bio).await?
@@ -638,6 +988,7 @@ impl super::CommandExecutor for WalletSubcmd {
             }
             WalletSubcmd::SendBitcoins {
                 recipient,
+                recipients_file,
                 fee_rate,
                 fee_absolute,
                 include,
@@ -647,19 +998,108 @@ impl super::CommandExecutor for WalletSubcmd {
                 sign,
                 broadcast,
                 skip_confirmation,
+                psbt_output,
             } => {
-                // Check every addresses against the Network
+                let batch = match &recipients_file {
+                    Some(path) => read_recipients_file(path)?,
+                    None => vec![],
+                };
+                let labels = batch.iter().map(|br| br.label.clone()).collect::<Vec<_>>();
+
+                // Resolve the contacts and check every addresses against the Network
                 let recipient = recipient
                     .into_iter()
-                    .map(|(ad, am)| {
-                        Ok((
-                            ad.require_network(bitcoin_network::get())
-                                .map_err(|e| Error::InvalidAddressNetwork(e.to_string()))?,
-                            am,
-                        ))
-                    })
+                    .chain(batch.into_iter().map(|br| (br.address, br.amount)))
+                    .map(|(ad, am)| Ok((ad.resolve(&db)?, am)))
                     .collect::<Result<Vec<_>>>()?;
 
+                // Paying the same address twice is most likely a mistake in the recipients
+                let mut seen = HashSet::new();
+                if let Some((duplicate, _)) = recipient
+                    .iter()
+                    .find(|(ad, _)| !seen.insert(ad.to_string()))
+                {
+                    return Err(Error::Generic(format!(
+                        "{duplicate} appears more than once in the recipients"
+                    )));
+                }
+
+                // Percentages are of the spendable balance of the wallet, which excludes
+                // the frozen UTXOs that are not explicitly included
+                let spendable = if recipient
+                    .iter()
+                    .any(|(_, am)| matches!(am, RecipientAmount::Percent(_)))
+                {
+                    let balance = wallet.get_wallet_status().await?.balance;
+                    let frozen = WalletLabels::load_or_default(&db, wallet.name())?.frozen;
+                    let frozen_amount = wallet
+                        .online_wallet()
+                        .list_heritage_utxos()
+                        .await?
+                        .into_iter()
+                        .filter(|utxo| {
+                            frozen.contains(&utxo.outpoint) && !include.contains(&utxo.outpoint)
+                        })
+                        .map(|utxo| utxo.amount.to_sat())
+                        .sum::<u64>();
+                    (balance.uptodate().get_spendable() + balance.obsolete().get_spendable())
+                        .saturating_sub(frozen_amount)
+                } else {
+                    0
+                };
+                let fee_included = recipient
+                    .iter()
+                    .position(|(_, am)| matches!(am, RecipientAmount::FeeIncluded(_)));
+                if recipient
+                    .iter()
+                    .filter(|(_, am)| matches!(am, RecipientAmount::FeeIncluded(_)))
+                    .count()
+                    > 1
+                {
+                    return Err(Error::Generic(
+                        "The fee can only be deducted from the amount of one recipient".to_owned(),
+                    ));
+                }
+                // The recipients whose final amount is computed, to display it
+                let computed_amounts = recipient
+                    .iter()
+                    .filter_map(|(ad, am)| match am {
+                        RecipientAmount::Percent(percent) => {
+                            Some((ad.clone(), format!("{percent}% of the spendable balance")))
+                        }
+                        RecipientAmount::FeeIncluded(amount) => {
+                            Some((ad.clone(), format!("{} sat minus the fee", amount.to_sat())))
+                        }
+                        RecipientAmount::Fixed(_) | RecipientAmount::All => None,
+                    })
+                    .collect::<Vec<_>>();
+                let recipient = recipient
+                    .into_iter()
+                    .map(|(ad, am)| (ad, am.resolve(spendable)))
+                    .collect::<Vec<_>>();
+
+                // The batch recipients are the last ones, with their labels
+                let batch_summary = recipients_file.is_some().then(|| {
+                    let total = recipient
+                        .iter()
+                        .filter_map(|(_, am)| am.map(|am| am.to_sat()))
+                        .sum::<u64>();
+                    let mut lines = vec![format!(
+                        "\x1b[1mBatch payment\x1b[0m: {} recipient(s), {total} sat in total",
+                        recipient.len()
+                    )];
+                    let labeled = recipient.iter().skip(recipient.len() - labels.len());
+                    for ((address, amount), label) in labeled.zip(labels.iter()) {
+                        if let Some(label) = label {
+                            let amount = amount
+                                .map(|am| format!("{} sat", am.to_sat()))
+                                .unwrap_or("all".to_owned());
+                            lines.push(format!("  {address}: {amount} ({label})"));
+                        }
+                    }
+                    lines.join("\n")
+                });
+
                 // All recipients have an amount
                 // OR
                 // There is only one recipient
@@ -707,11 +1147,19 @@ impl super::CommandExecutor for WalletSubcmd {
                     .into_iter()
                     .collect::<Vec<_>>();
 
-                let exclude = exclude
-                    .into_iter()
-                    .collect::<HashSet<_>>()
-                    .into_iter()
-                    .collect::<Vec<_>>();
+                let exclude = if include_only {
+                    vec![]
+                } else {
+                    // Frozen UTXOs are excluded, unless explicitly included
+                    let wallet_labels = WalletLabels::load_or_default(&db, wallet.name())?;
+                    exclude
+                        .into_iter()
+                        .chain(wallet_labels.frozen.iter().copied())
+                        .filter(|outpoint| !include.contains(outpoint))
+                        .collect::<HashSet<_>>()
+                        .into_iter()
+                        .collect::<Vec<_>>()
+                };
 
                 let utxo_selection = if include.len() > 0 && exclude.len() > 0 {
                     Some(NewTxUtxoSelection::IncludeExclude { include, exclude })
@@ -730,17 +1178,168 @@ impl super::CommandExecutor for WalletSubcmd {
                 let disable_rbf = if disable_rbf { Some(true) } else { None };
 
                 // Get the PSBT
-                let (psbt, summary) = wallet
-                    .create_psbt(NewTx {
-                        spending_config,
-                        fee_policy,
-                        utxo_selection,
-                        disable_rbf,
+                let mut new_tx = NewTx {
+                    spending_config,
+                    fee_policy,
+                    utxo_selection,
+                    disable_rbf,
+                };
+                let (mut psbt, mut summary) = wallet.create_psbt(new_tx.clone()).await?;
+                if let Some(index) = fee_included {
+                    // Deduct the fee of the PSBT from the amount and create it again, until the
+                    // fee of the new PSBT is the deducted one
+                    let NewTxSpendingConfig::Recipients(mut recipients) =
+                        new_tx.spending_config.clone()
+                    else {
+                        unreachable!("every amount is some when the fee is deducted from one");
+                    };
+                    let address = recipients[index].address.clone();
+                    let amount = recipients[index].amount;
+                    let mut deducted_fee = 0;
+                    for _ in 0..FEE_INCLUDED_MAX_ATTEMPTS {
+                        if summary.fee.to_sat() == deducted_fee {
+                            break;
+                        }
+                        deducted_fee = summary.fee.to_sat();
+                        let remaining = amount.checked_sub(deducted_fee).ok_or_else(|| {
+                            Error::Generic(format!(
+                                "The amount for {address} cannot pay the {deducted_fee} sat fee"
+                            ))
+                        })?;
+                        if remaining < DUST_LIMIT {
+                            return Err(Error::Generic(format!(
+                                "The amount for {address} minus the {deducted_fee} sat fee \
+                                ({remaining} sat) is below the dust limit ({DUST_LIMIT} sat)"
+                            )));
+                        }
+                        recipients[index].amount = remaining;
+                        new_tx.spending_config =
+                            NewTxSpendingConfig::Recipients(recipients.clone());
+                        (psbt, summary) = wallet.create_psbt(new_tx.clone()).await?;
+                    }
+                    if summary.fee.to_sat() != deducted_fee {
+                        return Err(Error::Generic(format!(
+                            "Could not deduct the fee from the amount for {address}: \
+                            the fee changed from {deducted_fee} sat to {} sat",
+                            summary.fee.to_sat()
+                        )));
+                    }
+                }
+                let record = TransactionRecord::new(wallet.name(), &psbt, new_tx.spending_config);
+                for (address, description) in computed_amounts {
+                    let script_pubkey = address.script_pubkey();
+                    if let Some(txout) = psbt
+                        .unsigned_tx
+                        .output
+                        .iter()
+                        .find(|txout| txout.script_pubkey == script_pubkey)
+                    {
+                        interactive_println(&format!(
+                            "\x1b[1m{address}\x1b[0m: {description} = \x1b[1m{} sat\x1b[0m",
+                            txout.value
+                        ));
+                    }
+                }
+                if let Some(batch_summary) = batch_summary {
+                    interactive_println(&batch_summary);
+                    interactive_println(&format!(
+                        "\x1b[1mFee\x1b[0m: {} sat (~{:.1} sat/vB)",
+                        summary.fee.to_sat(),
+                        summary.fee.to_sat() as f64 / estimated_vsize(&psbt) as f64
+                    ));
+                }
+                let result = SpendFlow::new(psbt, bitcoin_network::get())
+                    .transaction_summary(&summary)
+                    .fingerprints(&get_fingerprints(&db).await?)
+                    .contacts(&get_contacts(&db)?)
+                    .labels(&get_labels(&db, wallet.name())?)
+                    .current_fee_rate(current_fee_rate(&wallet).await)
+                    .display()
+                    .set_sign(if sign {
+                        Some(wallet.key_provider())
+                    } else {
+                        None
                     })
+                    .set_broadcast(if broadcast {
+                        Some(wallet.online_wallet())
+                    } else {
+                        None
+                    })
+                    .set_skip_confirmations(skip_confirmation)
+                    .psbt_output(&psbt_output)
+                    .run()
                     .await?;
-                SpendFlow::new(psbt, bitcoin_network::get())
+                record.store_if_signed(&mut db, sign, result.as_ref())?;
+                result
+            }
+            WalletSubcmd::BumpFee {
+                txid,
+                fee_rate,
+                sign,
+                broadcast,
+                skip_confirmation,
+                psbt_output,
+            } => {
+                let original_tx = unconfirmed_transaction(&wallet, txid).await?;
+                let Some(original) = TransactionRecord::find(&db, wallet.name(), txid)? else {
+                    return Err(Error::Generic(format!(
+                        "Transaction {txid} was not signed by this wallet, it cannot be replaced"
+                    )));
+                };
+                let original_fee_rate =
+                    original_tx.fee.to_sat() as f64 / estimated_vsize(&original.psbt) as f64;
+                if fee_rate as f64 <= original_fee_rate {
+                    return Err(Error::Generic(format!(
+                        "The fee rate must be higher than the one of the original transaction \
+                        (~{original_fee_rate:.1} sat/vB)"
+                    )));
+                }
+                let original_inputs = original
+                    .psbt
+                    .unsigned_tx
+                    .input
+                    .iter()
+                    .map(|txin| txin.previous_output)
+                    .collect::<Vec<_>>();
+                // The added inputs can be neither frozen UTXOs nor the outputs
+                // of the original transaction, that the replacement will invalidate
+                let exclude = WalletLabels::load_or_default(&db, wallet.name())?
+                    .frozen
+                    .into_iter()
+                    .filter(|outpoint| !original_inputs.contains(outpoint))
+                    .chain(
+                        (0..original.psbt.unsigned_tx.output.len() as u32)
+                            .map(|vout| OutPoint { txid, vout }),
+                    )
+                    .collect::<Vec<_>>();
+                // Pay again every recipient of the original transaction, even those belonging
+                // to the wallet: only the change is computed again.
+                // The replacement must spend every input of the original transaction,
+                // more can be added if the change cannot cover the additional fee
+                let spending_config = original.spending_config;
+                let utxo_selection = match &spending_config {
+                    NewTxSpendingConfig::Recipients(_) => NewTxUtxoSelection::IncludeExclude {
+                        include: original_inputs,
+                        exclude,
+                    },
+                    NewTxSpendingConfig::DrainTo(_) => NewTxUtxoSelection::UseOnly {
+                        use_only: original_inputs,
+                    },
+                };
+                let new_tx = NewTx {
+                    spending_config: spending_config.clone(),
+                    fee_policy: Some(NewTxFeePolicy::Rate { rate: fee_rate }),
+                    utxo_selection: Some(utxo_selection),
+                    disable_rbf: None,
+                };
+                let (psbt, summary) = create_replacement(&wallet, &original_tx, new_tx).await?;
+                let record = TransactionRecord::new(wallet.name(), &psbt, spending_config);
+                let result = SpendFlow::new(psbt, bitcoin_network::get())
                     .transaction_summary(&summary)
                     .fingerprints(&get_fingerprints(&db).await?)
+                    .contacts(&get_contacts(&db)?)
+                    .labels(&get_labels(&db, wallet.name())?)
+                    .current_fee_rate(current_fee_rate(&wallet).await)
                     .display()
                     .set_sign(if sign {
                         Some(wallet.key_provider())
@@ -753,16 +1352,213 @@ impl super::CommandExecutor for WalletSubcmd {
                         None
                     })
                     .set_skip_confirmations(skip_confirmation)
+                    .psbt_output(&psbt_output)
                     .run()
-                    .await?
+                    .await?;
+                record.store_if_signed(&mut db, sign, result.as_ref())?;
+                result
+            }
+            WalletSubcmd::Cpfp {
+                txid,
+                fee_rate,
+                sign,
+                broadcast,
+                skip_confirmation,
+                psbt_output,
+            } => {
+                let parent_tx = unconfirmed_transaction(&wallet, txid).await?;
+                // The size of the parent comes from its record
+                let Some(parent) = TransactionRecord::find(&db, wallet.name(), txid)? else {
+                    return Err(Error::Generic(format!(
+                        "Transaction {txid} was not signed by this wallet, \
+                        its size is unknown and it cannot be accelerated"
+                    )));
+                };
+                let frozen = WalletLabels::load_or_default(&db, wallet.name())?.frozen;
+                let change = parent_tx
+                    .owned_outputs
+                    .iter()
+                    .filter(|o| o.outpoint.txid == txid && !frozen.contains(&o.outpoint))
+                    .collect::<Vec<_>>();
+                if change.is_empty() {
+                    return Err(Error::Generic(format!(
+                        "Transaction {txid} has no unfrozen output returning to the wallet, \
+                        use \"bump-fee\" instead"
+                    )));
+                }
+                let change_amount = change.iter().map(|o| o.amount.to_sat()).sum::<u64>();
+
+                let parent_vsize = estimated_vsize(&parent.psbt);
+                let parent_fee = parent_tx.fee.to_sat();
+                let drain_to =
+                    wallet_address_to_string(&wallet.online_wallet().get_address().await?);
+                // Start with the size of a child spending the change outputs with the key path
+                // to a single P2TR output: ~10.5 vB of overhead, ~57.5 vB per input and 43 vB
+                // for the output. Then adjust the fee to the actual size of the child.
+                let mut child_vsize = 11 + 58 * change.len() as u64 + 43;
+                let mut attempts = 0;
+                let (psbt, summary, child_fee) = loop {
+                    let package_fee =
+                        (fee_rate as f64 * (parent_vsize + child_vsize) as f64).ceil() as u64;
+                    // The child must pay at least 1 sat/vB for itself
+                    if package_fee < parent_fee + child_vsize {
+                        return Err(Error::Generic(format!(
+                            "Transaction {txid} already pays ~{:.1} sat/vB",
+                            parent_fee as f64 / parent_vsize as f64
+                        )));
+                    }
+                    let child_fee = package_fee - parent_fee;
+                    // Keep the child output above the dust limit of P2TR outputs
+                    if child_fee + DUST_LIMIT > change_amount {
+                        return Err(Error::Generic(format!(
+                            "The change of transaction {txid} ({change_amount} sat) cannot pay \
+                            the {child_fee} sat fee needed to reach {fee_rate} sat/vB"
+                        )));
+                    }
+                    let (psbt, summary) = wallet
+                        .create_psbt(NewTx {
+                            spending_config: NewTxSpendingConfig::DrainTo(NewTxDrainTo {
+                                drain_to: drain_to.clone(),
+                            }),
+                            fee_policy: Some(NewTxFeePolicy::Absolute { amount: child_fee }),
+                            utxo_selection: Some(NewTxUtxoSelection::UseOnly {
+                                use_only: change.iter().map(|o| o.outpoint).collect(),
+                            }),
+                            disable_rbf: None,
+                        })
+                        .await?;
+                    let actual_vsize = estimated_vsize(&psbt);
+                    if actual_vsize <= child_vsize {
+                        break (psbt, summary, child_fee);
+                    }
+                    attempts += 1;
+                    if attempts == 5 {
+                        return Err(Error::Generic(
+                            "Could not compute the fee of the child transaction".to_owned(),
+                        ));
+                    }
+                    child_vsize = actual_vsize;
+                };
+                interactive_println(&format!(
+                    "The child transaction pays {child_fee} sat so that, with its parent, \
+                    they pay ~{fee_rate} sat/vB"
+                ));
+                let record = TransactionRecord::new(
+                    wallet.name(),
+                    &psbt,
+                    NewTxSpendingConfig::DrainTo(NewTxDrainTo { drain_to }),
+                );
+                let result = SpendFlow::new(psbt, bitcoin_network::get())
+                    .transaction_summary(&summary)
+                    .fingerprints(&get_fingerprints(&db).await?)
+                    .current_fee_rate(current_fee_rate(&wallet).await)
+                    .display()
+                    .set_sign(if sign {
+                        Some(wallet.key_provider())
+                    } else {
+                        None
+                    })
+                    .set_broadcast(if broadcast {
+                        Some(wallet.online_wallet())
+                    } else {
+                        None
+                    })
+                    .set_skip_confirmations(skip_confirmation)
+                    .psbt_output(&psbt_output)
+                    .run()
+                    .await?;
+                record.store_if_signed(&mut db, sign, result.as_ref())?;
+                result
+            }
+            WalletSubcmd::CancelTx {
+                txid,
+                fee_rate,
+                sign,
+                broadcast,
+                skip_confirmation,
+                psbt_output,
+            } => {
+                let original_tx = unconfirmed_transaction(&wallet, txid).await?;
+                let drain_to =
+                    wallet_address_to_string(&wallet.online_wallet().get_address().await?);
+                let spending_config = NewTxSpendingConfig::DrainTo(NewTxDrainTo {
+                    drain_to: drain_to.clone(),
+                });
+                let new_tx = NewTx {
+                    spending_config: spending_config.clone(),
+                    fee_policy: fee_rate.map(|rate| NewTxFeePolicy::Rate { rate }),
+                    utxo_selection: Some(NewTxUtxoSelection::UseOnly {
+                        use_only: original_tx
+                            .owned_inputs
+                            .iter()
+                            .map(|i| i.outpoint)
+                            .collect(),
+                    }),
+                    disable_rbf: None,
+                };
+                let (psbt, summary) = create_replacement(&wallet, &original_tx, new_tx).await?;
+                let record = TransactionRecord::new(wallet.name(), &psbt, spending_config);
+
+                let sum = |ios: &[TransactionSummaryOwnedIO]| {
+                    ios.iter().map(|io| io.amount.to_sat()).sum::<u64>()
+                };
+                let original_fee = original_tx.fee.to_sat();
+                let spent = sum(&original_tx.owned_inputs);
+                let sent = spent.saturating_sub(sum(&original_tx.owned_outputs) + original_fee);
+                let fee = psbt.fee().map_err(Error::generic)?.to_sat();
+                interactive_println(&format!(
+                    "\x1b[1mOriginal transaction\x1b[0m {txid}:\n  \
+                    spends {} input(s) for {spent} sat\n  \
+                    \x1b[31msends {sent} sat out of the wallet\x1b[0m\n  \
+                    pays a {original_fee} sat fee\n\
+                    \x1b[1mCancelling transaction\x1b[0m:\n  \
+                    spends the same input(s)\n  \
+                    \x1b[32msends {} sat back to {drain_to}\x1b[0m\n  \
+                    pays a {fee} sat fee (+{} sat)",
+                    original_tx.owned_inputs.len(),
+                    spent.saturating_sub(fee),
+                    fee - original_fee,
+                ));
+                if !skip_confirmation
+                    && !ask_user_confirmation(&format!("Do you want to cancel transaction {txid}?"))
+                        .await?
+                {
+                    return Ok(Box::new(Unsuccessful::cancelled(
+                        "Transaction cancellation aborted",
+                    )));
+                }
+                let result = SpendFlow::new(psbt, bitcoin_network::get())
+                    .transaction_summary(&summary)
+                    .fingerprints(&get_fingerprints(&db).await?)
+                    .current_fee_rate(current_fee_rate(&wallet).await)
+                    .display()
+                    .set_sign(if sign {
+                        Some(wallet.key_provider())
+                    } else {
+                        None
+                    })
+                    .set_broadcast(if broadcast {
+                        Some(wallet.online_wallet())
+                    } else {
+                        None
+                    })
+                    .set_skip_confirmations(skip_confirmation)
+                    .psbt_output(&psbt_output)
+                    .run()
+                    .await?;
+                record.store_if_signed(&mut db, sign, result.as_ref())?;
+                result
             }
             WalletSubcmd::SignPsbt {
                 psbt,
                 broadcast,
                 skip_confirmation,
+                psbt_output,
             } => {
-                SpendFlow::new(psbt, bitcoin_network::get())
+                SpendFlow::new(psbt.load()?, bitcoin_network::get())
                     .fingerprints(&get_fingerprints(&db).await?)
+                    .contacts(&get_contacts(&db)?)
+                    .labels(&get_labels(&db, wallet.name())?)
                     .sign(wallet.key_provider())
                     .set_skip_confirmations(skip_confirmation)
                     .set_broadcast(if broadcast {
@@ -770,11 +1566,12 @@ impl super::CommandExecutor for WalletSubcmd {
                     } else {
                         None
                     })
+                    .psbt_output(&psbt_output)
                     .run()
                     .await?
             }
             WalletSubcmd::BroadcastPsbt { psbt } => {
-                SpendFlow::<AnyKeyProvider, _>::new(psbt, bitcoin_network::get())
+                SpendFlow::<AnyKeyProvider, _>::new(psbt.load()?, bitcoin_network::get())
                     .broadcast(wallet.online_wallet())
                     .run()
                     .await?
@@ -784,7 +1581,123 @@ impl super::CommandExecutor for WalletSubcmd {
     }
 }
 
-fn parse_recipient(val: &str) -> Result<(Address<NetworkUnchecked>, Option<Amount>)> {
+/// A recipient of a batch payment, read from a recipients file
+#[derive(Debug, Clone)]
+struct BatchRecipient {
+    address: RecipientAddress,
+    amount: RecipientAmount,
+    label: Option<String>,
+}
+
+/// A recipient of a batch payment in a JSON recipients file
+#[derive(Debug, serde::Deserialize)]
+struct JsonBatchRecipient {
+    address: String,
+    amount: String,
+    label: Option<String>,
+}
+
+impl TryFrom<JsonBatchRecipient> for BatchRecipient {
+    type Error = Error;
+
+    fn try_from(value: JsonBatchRecipient) -> Result<Self> {
+        Ok(BatchRecipient {
+            address: RecipientAddress::from_str(&value.address)?,
+            amount: parse_amount(&value.amount)?,
+            label: value.label.filter(|label| !label.is_empty()),
+        })
+    }
+}
+
+/// Read a recipients file, JSON if its extension is .json, CSV otherwise
+fn read_recipients_file(path: &Path) -> Result<Vec<BatchRecipient>> {
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        log::error!("Could not read {}: {e}", path.display());
+        Error::generic(e)
+    })?;
+    let recipients = if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str::<Vec<JsonBatchRecipient>>(&content)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, recipient)| {
+                BatchRecipient::try_from(recipient)
+                    .map_err(|e| Error::Generic(format!("Recipient #{}: {e}", i + 1)))
+            })
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            // The header line is optional
+            .filter(|(i, line)| !(*i == 0 && line.trim().to_lowercase().starts_with("address")))
+            .map(|(i, line)| {
+                parse_csv_recipient(line)
+                    .map_err(|e| Error::Generic(format!("Line {}: {e}", i + 1)))
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+    if recipients.is_empty() {
+        return Err(Error::Generic(format!(
+            "No recipient in {}",
+            path.display()
+        )));
+    }
+    Ok(recipients)
+}
+
+/// Parse a <ADDRESS>,<AMOUNT>[,<LABEL>] CSV line, the label can be double-quoted
+fn parse_csv_recipient(line: &str) -> Result<BatchRecipient> {
+    let mut parts = line.splitn(3, ',').map(str::trim);
+    let (Some(address), Some(amount)) = (parts.next(), parts.next()) else {
+        return Err(Error::Generic(
+            "invalid recipient. Must be <ADDRESS>,<AMOUNT>[,<LABEL>]".to_owned(),
+        ));
+    };
+    let label = parts
+        .next()
+        .map(|label| {
+            label
+                .strip_prefix('"')
+                .and_then(|label| label.strip_suffix('"'))
+                .unwrap_or(label)
+                .replace("\"\"", "\"")
+        })
+        .filter(|label| !label.is_empty());
+    Ok(BatchRecipient {
+        address: RecipientAddress::from_str(address)?,
+        amount: parse_amount(amount)?,
+        label,
+    })
+}
+
+/// The amount to send to a recipient
+#[derive(Debug, Clone, Copy)]
+pub(super) enum RecipientAmount {
+    Fixed(Amount),
+    /// A percentage of the spendable balance of the wallet
+    Percent(f64),
+    /// An amount from which the fee of the transaction is deducted
+    FeeIncluded(Amount),
+    /// Whatever remains, draining the wallet
+    All,
+}
+
+impl RecipientAmount {
+    /// The amount to put in the transaction, None meaning 'all'.
+    /// The fee is deducted later, once known.
+    fn resolve(self, spendable: u64) -> Option<Amount> {
+        match self {
+            RecipientAmount::Fixed(amount) | RecipientAmount::FeeIncluded(amount) => Some(amount),
+            RecipientAmount::Percent(percent) => Some(Amount::from_sat(
+                (spendable as f64 * percent / 100.0).floor() as u64,
+            )),
+            RecipientAmount::All => None,
+        }
+    }
+}
+
+pub(super) fn parse_recipient(val: &str) -> Result<(RecipientAddress, RecipientAmount)> {
     if !val.contains(':') {
         return Err(Error::Generic(
             "invalid recipient. Must be <ADDRESS>:<AMOUNT>".to_owned(),
@@ -795,15 +1708,12 @@ fn parse_recipient(val: &str) -> Result<(Address<NetworkUnchecked>, Option<Amoun
     let addr = parts.next().ok_or_else(|| {
         Error::Generic("invalid recipient. Must be <ADDRESS>:<AMOUNT>".to_owned())
     })?;
-    let addr = Address::from_str(addr).map_err(Error::generic)?;
+    let addr = RecipientAddress::from_str(addr)?;
 
     let amount = parts.next().ok_or_else(|| {
         Error::Generic("invalid recipient. Must be <ADDRESS>:<AMOUNT>".to_owned())
     })?;
-    let amount = match amount {
-        "all" => None,
-        _ => Some(amount.parse::<Amount>().map_err(Error::generic)?),
-    };
+    let amount = parse_amount(amount)?;
 
     if parts.next().is_some() {
         return Err(Error::Generic(
@@ -814,7 +1724,102 @@ fn parse_recipient(val: &str) -> Result<(Address<NetworkUnchecked>, Option<Amoun
     Ok((addr, amount))
 }
 
-fn parse_fee_rate(val: &str) -> Result<f32> {
+/// Parse the amount of a recipient: a quantity of BTC, a percentage, a quantity of BTC
+/// suffixed by '-fee' or 'all'
+fn parse_amount(amount: &str) -> Result<RecipientAmount> {
+    if amount == "all" {
+        Ok(RecipientAmount::All)
+    } else if let Some(percent) = amount.strip_suffix('%') {
+        let percent = percent.parse::<f64>().map_err(Error::generic)?;
+        if percent > 0.0 && percent < 100.0 {
+            Ok(RecipientAmount::Percent(percent))
+        } else {
+            Err(Error::Generic(
+                "A percentage must be between 0% and 100% excluded, use 'all' to drain the wallet"
+                    .to_owned(),
+            ))
+        }
+    } else if let Some(amount) = amount.strip_suffix("-fee") {
+        Ok(RecipientAmount::FeeIncluded(
+            amount
+                .trim_end()
+                .parse::<Amount>()
+                .map_err(Error::generic)?,
+        ))
+    } else {
+        Ok(RecipientAmount::Fixed(
+            amount.parse::<Amount>().map_err(Error::generic)?,
+        ))
+    }
+}
+
+/// Find an unconfirmed transaction in the wallet history, verifying that the given PSBT,
+/// if any, is the one of this transaction
+async fn unconfirmed_transaction(wallet: &Wallet, txid: Txid) -> Result<TransactionSummary> {
+    let tx_summary = wallet
+        .online_wallet()
+        .list_transactions()
+        .await?
+        .into_iter()
+        .find(|tx_summary| tx_summary.txid == txid)
+        .ok_or_else(|| Error::Generic(format!("Transaction {txid} is not in the wallet")))?;
+    if tx_summary.confirmation_time.is_some() {
+        return Err(Error::Generic(format!(
+            "Transaction {txid} is already confirmed"
+        )));
+    }
+    Ok(tx_summary)
+}
+
+/// How many times a PSBT is created again to deduct its fee from the amount of a recipient
+const FEE_INCLUDED_MAX_ATTEMPTS: usize = 4;
+
+/// The fee rate, in sat/vB, a replacement transaction must pay for its own size on top of
+/// the fee of the original transaction (BIP125 rule 4)
+const INCREMENTAL_RELAY_FEE_RATE: u64 = 1;
+
+/// Create a transaction replacing an unconfirmed one of the wallet, raising its fee if it
+/// is not enough to replace the original transaction
+async fn create_replacement(
+    wallet: &Wallet,
+    original_tx: &TransactionSummary,
+    mut new_tx: NewTx,
+) -> Result<(Psbt, TransactionSummary)> {
+    let (mut psbt, mut summary) = wallet.create_psbt(new_tx.clone()).await?;
+    let min_fee = replacement_min_fee(original_tx, &psbt);
+    if summary.fee.to_sat() < min_fee {
+        log::info!(
+            "Raising the fee to {min_fee} sat to replace transaction {}",
+            original_tx.txid
+        );
+        new_tx.fee_policy = Some(NewTxFeePolicy::Absolute { amount: min_fee });
+        (psbt, summary) = wallet.create_psbt(new_tx).await?;
+    }
+    verify_replacement_fee(original_tx, &psbt)?;
+    Ok((psbt, summary))
+}
+
+/// The minimum fee of a replacement transaction (BIP125 rule 4)
+fn replacement_min_fee(original_tx: &TransactionSummary, replacement: &Psbt) -> u64 {
+    original_tx.fee.to_sat() + INCREMENTAL_RELAY_FEE_RATE * estimated_vsize(replacement)
+}
+
+/// Verify that a replacement transaction pays enough fee to replace the original, as required by BIP125
+fn verify_replacement_fee(original_tx: &TransactionSummary, replacement: &Psbt) -> Result<()> {
+    let fee = replacement.fee().map_err(Error::generic)?.to_sat();
+    let min_fee = replacement_min_fee(original_tx, replacement);
+    if fee < min_fee {
+        return Err(Error::Generic(format!(
+            "The replacement transaction fee ({fee} sat) must be at least {min_fee} sat: \
+            the fee of the original transaction ({} sat) plus {INCREMENTAL_RELAY_FEE_RATE} sat/vB \
+            for the replacement transaction",
+            original_tx.fee.to_sat()
+        )));
+    }
+    Ok(())
+}
+
+pub(super) fn parse_fee_rate(val: &str) -> Result<f32> {
     let val = val.parse::<f32>().map_err(Error::generic)?;
     if val >= 1.0 {
         Ok(val)
@@ -822,3 +1827,86 @@ fn parse_fee_rate(val: &str) -> Result<f32> {
         Err(Error::generic("Fee rate must be greater or equal to 1.0"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr";
+
+    #[test]
+    fn last_sync_never_synced() {
+        assert_eq!(format_last_sync(0), "never synced");
+        assert_eq!(format_last_sync(1_700_000_000), "2023-11-14 22:13:20 UTC");
+    }
+
+    #[test]
+    fn csv_recipient_without_label() {
+        let recipient = parse_csv_recipient(&format!("{ADDRESS}, 1000 sat")).unwrap();
+        assert!(matches!(recipient.address, RecipientAddress::Address(_)));
+        assert!(
+            matches!(recipient.amount, RecipientAmount::Fixed(amount) if amount.to_sat() == 1000)
+        );
+        assert_eq!(recipient.label, None);
+
+        let recipient = parse_csv_recipient(&format!("{ADDRESS},1000 sat,")).unwrap();
+        assert_eq!(recipient.label, None);
+    }
+
+    #[test]
+    fn csv_recipient_with_label() {
+        let recipient = parse_csv_recipient("@alice,1000 sat,Rent, March").unwrap();
+        assert!(
+            matches!(recipient.address, RecipientAddress::Contact(ref name) if name == "alice")
+        );
+        assert_eq!(recipient.label.as_deref(), Some("Rent, March"));
+
+        let recipient = parse_csv_recipient(r#"@alice,1000 sat,"The ""big"" one""#).unwrap();
+        assert_eq!(recipient.label.as_deref(), Some(r#"The "big" one"#));
+    }
+
+    #[test]
+    fn csv_recipient_invalid() {
+        assert!(parse_csv_recipient(ADDRESS).is_err());
+        assert!(parse_csv_recipient(&format!("{ADDRESS},lots")).is_err());
+        assert!(parse_csv_recipient("not-an-address,1000 sat").is_err());
+    }
+
+    #[test]
+    fn amounts() {
+        assert!(matches!(parse_amount("all"), Ok(RecipientAmount::All)));
+        assert!(matches!(
+            parse_amount("0.001 BTC"),
+            Ok(RecipientAmount::Fixed(amount)) if amount.to_sat() == 100_000
+        ));
+        assert!(matches!(
+            parse_amount("1000 sat-fee"),
+            Ok(RecipientAmount::FeeIncluded(amount)) if amount.to_sat() == 1000
+        ));
+        assert!(matches!(parse_amount("12.5%"), Ok(RecipientAmount::Percent(p)) if p == 12.5));
+    }
+
+    #[test]
+    fn invalid_amounts() {
+        assert!(parse_amount("0%").is_err());
+        assert!(parse_amount("100%").is_err());
+        assert!(parse_amount("-5%").is_err());
+        assert!(parse_amount("ten%").is_err());
+        assert!(parse_amount("-fee").is_err());
+        assert!(parse_amount("~1000 sat").is_err());
+        assert!(parse_amount("1000").is_err());
+    }
+
+    #[test]
+    fn resolved_amounts() {
+        assert_eq!(RecipientAmount::All.resolve(10_000), None);
+        assert_eq!(
+            RecipientAmount::Percent(12.5).resolve(10_001),
+            Some(Amount::from_sat(1_250))
+        );
+        assert_eq!(
+            RecipientAmount::FeeIncluded(Amount::from_sat(1_000)).resolve(10_000),
+            Some(Amount::from_sat(1_000))
+        );
+    }
+}
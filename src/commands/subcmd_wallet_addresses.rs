@@ -0,0 +1,104 @@
+use core::any::Any;
+
+use btc_heritage_wallet::{
+    bitcoin::{address::NetworkUnchecked, bip32::ChildNumber, Address},
+    btc_heritage::{heritage_wallet::WalletAddress, utils::bitcoin_network},
+    errors::{Error, Result},
+    AnyKeyProvider, Database, OnlineWallet, Wallet,
+};
+
+use crate::{display::interactive_println, utils::wallet_address_to_string};
+
+/// Wallet addresses subcommand.
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum WalletAddressSubcmd {
+    /// Display an address of the wallet on the Ledger device, to confirm on its screen
+    /// that the address really belongs to the wallet.
+    /// {n}The Ledger policy of the address must be registered (see the "ledger-policies" command)
+    Verify {
+        /// The address to verify
+        address: Address<NetworkUnchecked>,
+    },
+}
+
+impl super::CommandExecutor for WalletAddressSubcmd {
+    async fn execute(
+        self,
+        params: Box<dyn Any + Send>,
+    ) -> Result<Box<dyn crate::display::Displayable>> {
+        let (wallet, _db): (Wallet, Database) = *params.downcast().unwrap();
+
+        let res: Box<dyn crate::display::Displayable> = match self {
+            WalletAddressSubcmd::Verify { address } => {
+                let address = address
+                    .require_network(bitcoin_network::get())
+                    .map_err(|e| Error::InvalidAddressNetwork(e.to_string()))?
+                    .to_string();
+                let wallet_address = wallet
+                    .online_wallet()
+                    .list_addresses()
+                    .await?
+                    .into_iter()
+                    .find(|wa| wallet_address_to_string(wa) == address)
+                    .ok_or_else(|| {
+                        Error::Generic(format!("{address} is not an address of the wallet"))
+                    })?;
+                verify_on_ledger(&wallet, &wallet_address).await?;
+                Box::new("Address verified on the Ledger")
+            }
+        };
+        Ok(res)
+    }
+}
+
+/// Make the Ledger of the wallet derive and display the address using the registered
+/// Ledger policy of its account, for the user to confirm it on the device.
+/// Fails if the Ledger does not derive the same address.
+pub(super) async fn verify_on_ledger(
+    wallet: &Wallet,
+    wallet_address: &WalletAddress,
+) -> Result<()> {
+    let AnyKeyProvider::Ledger(ledger_wallet) = wallet.key_provider() else {
+        return Err(Error::IncorrectKeyProvider("Ledger"));
+    };
+    let address = wallet_address_to_string(wallet_address);
+    let (fingerprint, account_index) = key_origin(wallet_address)?;
+    if fingerprint != wallet.fingerprint()?.to_string() {
+        return Err(Error::Generic(format!(
+            "{address} is not derived from the key of the Ledger ({fingerprint})"
+        )));
+    }
+    if !ledger_wallet
+        .list_registered_policies()
+        .iter()
+        .any(|(id, ..)| *id == account_index)
+    {
+        return Err(Error::Generic(format!(
+            "The Ledger policy of account #{account_index} is not registered. \
+            Register it with the \"ledger-policies auto-register\" command"
+        )));
+    }
+
+    interactive_println(&format!(
+        "Check that your Ledger displays the address {address} and approve it"
+    ));
+    let displayed = ledger_wallet.display_address(wallet_address).await?;
+    if displayed.to_string() != address {
+        return Err(Error::Generic(format!(
+            "The Ledger derived {displayed} instead of {address}. DO NOT USE THIS ADDRESS"
+        )));
+    }
+    Ok(())
+}
+
+/// The fingerprint and account index of the key origin of a [WalletAddress],
+/// derived as <fingerprint>/<purpose>'/<coin_type>'/<account>'/<change>/<index>
+fn key_origin(wallet_address: &WalletAddress) -> Result<(String, u32)> {
+    let (fingerprint, path) = wallet_address.origin();
+    match path.as_ref().get(2) {
+        Some(ChildNumber::Hardened { index }) => Ok((fingerprint.to_string(), *index)),
+        _ => Err(Error::Generic(format!(
+            "Invalid key origin for {wallet_address}"
+        ))),
+    }
+}
@@ -18,12 +18,22 @@ pub enum WalletAXpubSubcmd {
     },
     /// List the Account eXtended Public Keys already added by the Online component of the wallet and their status
     ListAdded {
-        /// List the used Account eXtended Public Keys of the Online wallet
-        #[arg(long, default_value_t = true)]
+        /// Only list the used Account eXtended Public Keys of the Online wallet
+        #[arg(long, default_value_t = false, conflicts_with = "unused")]
         used: bool,
-        /// List the unused Account eXtended Public Keys of the Online wallet
-        #[arg(long, default_value_t = true)]
+        /// Only list the unused Account eXtended Public Keys of the Online wallet
+        #[arg(long, default_value_t = false)]
         unused: bool,
+        /// Only list the Account eXtended Public Keys with a descriptor index greater or equal to this one
+        #[arg(long, value_name = "INDEX")]
+        from: Option<u32>,
+        /// Only list the Account eXtended Public Keys with a descriptor index lower or equal to this one
+        #[arg(long, value_name = "INDEX")]
+        to: Option<u32>,
+        /// Display the number of used and unused Account eXtended Public Keys
+        /// and the next index the "auto-add" command would derive, instead of the list
+        #[arg(long, default_value_t = false)]
+        summary: bool,
     },
     /// Add Account eXtended Public Keys to the Online component of the wallet
     Add {
@@ -46,27 +56,41 @@ impl super::CommandExecutor for WalletAXpubSubcmd {
     ) -> Result<Box<dyn crate::display::Displayable>> {
         let mut wallet: Wallet = *params.downcast().unwrap();
         let res: Box<dyn crate::display::Displayable> = match self {
-            WalletAXpubSubcmd::ListAdded { used, unused } => {
-                let mut res = wallet.list_account_xpubs().await?;
-                if !used {
-                    res.retain(|e| {
-                        match e {
-                            AccountXPubWithStatus::Used(_) => false,
-                            _ => true,
-                        };
-                        true
-                    })
-                }
-                if !unused {
-                    res.retain(|e| {
-                        match e {
-                            AccountXPubWithStatus::Unused(_) => false,
-                            _ => true,
-                        };
-                        true
+            WalletAXpubSubcmd::ListAdded {
+                used,
+                unused,
+                from,
+                to,
+                summary,
+            } => {
+                let axpubs = wallet.list_account_xpubs().await?;
+                let next_index = next_auto_add_index(&axpubs);
+                let mut res = axpubs;
+                res.retain(|e| {
+                    let status_matches = match e {
+                        AccountXPubWithStatus::Used(_) => !unused,
+                        AccountXPubWithStatus::Unused(_) => !used,
+                    };
+                    let index = descriptor_id(e);
+                    status_matches
+                        && from.map_or(true, |from| index >= from)
+                        && to.map_or(true, |to| index <= to)
+                });
+                if summary {
+                    Box::new(AccountXPubsSummary {
+                        used: res
+                            .iter()
+                            .filter(|e| matches!(e, AccountXPubWithStatus::Used(_)))
+                            .count(),
+                        unused: res
+                            .iter()
+                            .filter(|e| matches!(e, AccountXPubWithStatus::Unused(_)))
+                            .count(),
+                        next_index,
                     })
+                } else {
+                    Box::new(res)
                 }
-                Box::new(res)
             }
             WalletAXpubSubcmd::Generate { start, end } => {
                 Box::new(wallet.derive_accounts_xpubs(start..end).await?)
@@ -77,18 +101,11 @@ impl super::CommandExecutor for WalletAXpubSubcmd {
             }
             WalletAXpubSubcmd::AutoAdd { count } => {
                 let axpubs = wallet.list_account_xpubs().await?;
-                let (unused_count, last_seen_index) =
-                    axpubs
-                        .iter()
-                        .fold((0usize, None), |(uc, lsi), axpub| match axpub {
-                            AccountXPubWithStatus::Used(axpub) => {
-                                (uc, core::cmp::max(lsi, Some(axpub.descriptor_id())))
-                            }
-                            AccountXPubWithStatus::Unused(axpub) => {
-                                (uc + 1, core::cmp::max(lsi, Some(axpub.descriptor_id())))
-                            }
-                        });
-                let start = last_seen_index.map(|lsi| lsi + 1).unwrap_or(0);
+                let unused_count = axpubs
+                    .iter()
+                    .filter(|axpub| matches!(axpub, AccountXPubWithStatus::Unused(_)))
+                    .count();
+                let start = next_auto_add_index(&axpubs);
                 let end = start + (count.checked_sub(unused_count).unwrap_or(0)) as u32;
                 let account_xpubs = wallet.derive_accounts_xpubs(start..end).await?;
                 wallet.feed_account_xpubs(account_xpubs).await?;
@@ -99,6 +116,33 @@ impl super::CommandExecutor for WalletAXpubSubcmd {
     }
 }
 
+#[derive(Debug, serde::Serialize)]
+struct AccountXPubsSummary {
+    used: usize,
+    unused: usize,
+    /// The index of the next Account eXtended Public Key the "auto-add" command would derive
+    next_index: u32,
+}
+impl crate::display::SerdeDisplay for AccountXPubsSummary {}
+
+fn descriptor_id(axpub: &AccountXPubWithStatus) -> u32 {
+    match axpub {
+        AccountXPubWithStatus::Used(axpub) | AccountXPubWithStatus::Unused(axpub) => {
+            axpub.descriptor_id()
+        }
+    }
+}
+
+/// The index following the highest index of the Account eXtended Public Keys already added
+fn next_auto_add_index(axpubs: &[AccountXPubWithStatus]) -> u32 {
+    axpubs
+        .iter()
+        .map(descriptor_id)
+        .max()
+        .map(|lsi| lsi + 1)
+        .unwrap_or(0)
+}
+
 fn parse_account_xpubs(val: &str) -> Result<AccountXPub> {
     Ok(AccountXPub::try_from(val)?)
 }
@@ -1,15 +1,35 @@
 use core::any::Any;
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use btc_heritage_wallet::{
+    bitcoin::{Amount, Denomination},
     btc_heritage::{
         heritage_config::v1::{Days, Heritage},
+        utils::{bitcoin_network, timestamp_now},
         AccountXPub, HeirConfig, HeritageConfig, HeritageConfigVersion,
     },
     errors::{Error, Result},
+    heritage_service_api_client::{
+        NewTx, NewTxDrainTo, NewTxFeePolicy, NewTxSpendingConfig, NewTxUtxoSelection,
+    },
     AnyOnlineWallet, Database, DatabaseItem, Heir, OnlineWallet, Wallet,
 };
-use chrono::{NaiveDate, NaiveTime};
+use chrono::{DateTime, Months, NaiveDate, NaiveTime, Utc};
+
+use super::{
+    subcmd_contact::get_contacts,
+    subcmd_template::HeritageConfigTemplate,
+    subcmd_wallet_labels::{get_labels, WalletLabels},
+};
+use crate::{
+    display::{interactive_println, Displayable, Unsuccessful},
+    psbt_io::PsbtOutput,
+    spendflow::SpendFlow,
+    utils::{ask_user_confirmation, current_fee_rate, get_fingerprints, wallet_address_to_string},
+};
 
 /// Wallet Heritage Configuration management subcommand.
 #[derive(Debug, Clone, clap::Subcommand)]
@@ -18,15 +38,78 @@ pub enum WalletHeritageConfigSubcmd {
     List,
     /// Display the current Heritage Configuration of the wallet
     ShowCurrent,
+    /// Display, for every Heritage Configuration of the wallet, the calendar date at which
+    /// each Heir can spend the coins still locked by it
+    Timeline,
     /// Set a new Heritage Configuration of the wallet
     #[group(id = "heritage", multiple = true, required = false)]
     Set {
         /// The full Heritage Configuration as a JSON
-        #[arg(long, value_parser=parse_heritage_configuration, conflicts_with="manual_spec", required_unless_present="manual_spec", help_heading="JSON specification")]
+        #[arg(long, value_parser=parse_heritage_configuration, conflicts_with="manual_spec", required_unless_present_any=["manual_spec", "template", "file"], help_heading="JSON specification")]
+        json: Option<HeritageConfig>,
+        /// Use the Heirs, lock durations and minimum lock time of a template (see the "template" command).
+        /// {n}The reference date and minimum lock time can still be specified manually
+        #[arg(long, conflicts_with_all=["json", "local_heir", "service_heir", "heir_config"], help_heading="Template specification")]
+        template: Option<String>,
+        /// A YAML or TOML file describing the Heritage Configuration, with Heirs referenced by name.
+        /// {n}The format is deduced from the file extension (.yaml, .yml or .toml)
+        #[arg(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath, conflicts_with_all=["json", "template", "manual_spec"], help_heading="File specification")]
+        file: Option<PathBuf>,
+        #[command(flatten, next_help_heading = "Manual specification")]
+        manual_spec: Option<ManualSpec>,
+        /// Only display the differences with the current Heritage Configuration, do not set it
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+        /// Set the Heritage Configuration without displaying the differences with the current one
+        /// and asking for confirmation
+        #[arg(short = 'y', long, default_value_t = false)]
+        skip_confirmation: bool,
+    },
+    /// Compare an Heritage Configuration with the current one of the wallet,
+    /// without setting it
+    #[group(id = "heritage", multiple = true, required = false)]
+    Diff {
+        /// The full Heritage Configuration as a JSON
+        #[arg(long, value_parser=parse_heritage_configuration, conflicts_with="manual_spec", required_unless_present_any=["manual_spec", "template", "file"], help_heading="JSON specification")]
         json: Option<HeritageConfig>,
+        /// Use the Heirs, lock durations and minimum lock time of a template (see the "template" command).
+        /// {n}The reference date and minimum lock time can still be specified manually
+        #[arg(long, conflicts_with_all=["json", "local_heir", "service_heir", "heir_config"], help_heading="Template specification")]
+        template: Option<String>,
+        /// A YAML or TOML file describing the Heritage Configuration, with Heirs referenced by name.
+        /// {n}The format is deduced from the file extension (.yaml, .yml or .toml)
+        #[arg(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath, conflicts_with_all=["json", "template", "manual_spec"], help_heading="File specification")]
+        file: Option<PathBuf>,
         #[command(flatten, next_help_heading = "Manual specification")]
         manual_spec: Option<ManualSpec>,
     },
+    /// Re-create the current Heritage Configuration with the same Heirs and lock durations
+    /// but with today as the reference date, resetting the dead-man switch
+    Renew {
+        /// Also create a PSBT moving all the coins still locked by obsolete Heritage Configurations
+        /// to a new address of the renewed Heritage Configuration
+        #[arg(long, default_value_t = false)]
+        consolidate: bool,
+        /// Force the given fee rate, in sat/vB, for the consolidation transaction fee computation
+        #[arg(long, visible_alias = "fr", value_parser = super::subcmd_wallet::parse_fee_rate, requires = "consolidate")]
+        fee_rate: Option<f32>,
+        /// Immediately sign the consolidation PSBT
+        #[arg(short, long, default_value_t = false, requires = "consolidate")]
+        sign: bool,
+        /// Immediately broadcast the consolidation PSBT after signing it
+        #[arg(short, long, default_value_t = false, requires = "sign")]
+        broadcast: bool,
+        /// Only display the differences with the current Heritage Configuration, do not renew it
+        #[arg(long, default_value_t = false, conflicts_with = "consolidate")]
+        dry_run: bool,
+        /// Renew the Heritage Configuration without displaying the differences with the current one,
+        /// and sign or broadcast if requested, without asking for confirmation{n}
+        /// /!\ BE VERY CAREFULL with that option /!\.
+        #[arg(short = 'y', long, default_value_t = false)]
+        skip_confirmation: bool,
+        #[command(flatten, next_help_heading = "PSBT output")]
+        psbt_output: PsbtOutput,
+    },
 }
 
 #[derive(Debug, Clone, clap::Args)]
@@ -42,25 +125,343 @@ pub struct ManualSpec {
     /// {n}Usefull if an old address with an already passed absolute lock time receives coins. [default: 30 days]
     #[arg(short, long, value_name="DAYS", value_parser=parse_days)]
     minimum_lock_time: Option<Days>,
-    /// An Heir, consisting of an Heir and a lock duration in days.
+    /// An Heir, consisting of an Heir and a lock duration or an absolute date.
     /// {n}<LOCAL_HEIR> is a reference to a locally declared Heir (see the "heir" sub-command)
-    /// {n}<LOCK_DAYS> is the number of days from the `reference_date` before this Heir can access the funds
+    /// {n}<LOCK> is the duration from the `reference_date` before this Heir can access the funds,
+    /// in days (720 or 720d), weeks (6w), months (18m) or years (2y)
+    /// {n}<LOCAL_HEIR>@<DATE> can be used instead to specify the date from which this Heir can access the funds
     /// {n}Can be specified multiple times.
-    #[arg(long, visible_alias="lh", value_name="LOCAL_HEIR>:<LOCK_DAYS", value_parser=parse_heir, group("heritage"))]
-    local_heir: Vec<(String, Days)>,
-    /// An Heir, consisting of an Heir and a lock duration in days.
+    #[arg(long, visible_alias="lh", value_name="LOCAL_HEIR>:<LOCK", value_parser=parse_heir, group("heritage"))]
+    local_heir: Vec<(String, LockSpec)>,
+    /// An Heir, consisting of an Heir and a lock duration or an absolute date.
     /// {n}<SERVICE_HEIR> is a reference to a service declared Heir (see the "service heir" sub-command)
-    /// {n}<LOCK_DAYS> is the number of days from the `reference_date` before this Heir can access the funds
+    /// {n}<LOCK> is the duration from the `reference_date` before this Heir can access the funds,
+    /// in days (720 or 720d), weeks (6w), months (18m) or years (2y)
+    /// {n}<SERVICE_HEIR>@<DATE> can be used instead to specify the date from which this Heir can access the funds
     /// {n}Can be specified multiple times.
-    #[arg(long, visible_alias="sh", value_name="SERVICE_HEIR>:<LOCK_DAYS", value_parser=parse_heir, group("heritage"))]
-    service_heir: Vec<(String, Days)>,
-    /// An HeirConfig and a lock duration in days.
+    #[arg(long, visible_alias="sh", value_name="SERVICE_HEIR>:<LOCK", value_parser=parse_heir, group("heritage"))]
+    service_heir: Vec<(String, LockSpec)>,
+    /// An HeirConfig and a lock duration or an absolute date.
     /// {n}<KIND> is the kind of Heir Config (see the "heir" sub-command), can be ommited, defaults to xpub
     /// {n}<VALUE> is an appropriate string for the `KIND` of Heir Config
-    /// {n}<LOCK_DAYS> is the number of days from the `reference_date` before this Heir can access the funds
+    /// {n}<LOCK> is the duration from the `reference_date` before this Heir can access the funds,
+    /// in days (720 or 720d), weeks (6w), months (18m) or years (2y)
+    /// {n}<KIND>:<VALUE>@<DATE> can be used instead to specify the date from which this Heir can access the funds
     /// {n}Can be specified multiple times.
-    #[arg(long, visible_alias="hc", value_name="KIND>:<VALUE>:<LOCK_DAYS", value_parser=parse_heir_config, group="heritage")]
-    heir_config: Vec<(HeirConfig, Days)>,
+    #[arg(long, visible_alias="hc", value_name="KIND>:<VALUE>:<LOCK", value_parser=parse_heir_config, group="heritage")]
+    heir_config: Vec<(HeirConfig, LockSpec)>,
+}
+
+/// The lock of an Heir, relative to the reference date of the Heritage Configuration or absolute
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum LockSpec {
+    Days(u16),
+    Weeks(u16),
+    Months(u16),
+    Years(u16),
+    Date(NaiveDate),
+}
+impl LockSpec {
+    /// The number of days between the reference date and the end of the lock
+    fn resolve(&self, reference_date: NaiveDate) -> core::result::Result<u16, String> {
+        let end_date = match *self {
+            LockSpec::Days(days) => return Ok(days),
+            LockSpec::Weeks(weeks) => {
+                return weeks
+                    .checked_mul(7)
+                    .ok_or_else(|| format!("{self} is too long"))
+            }
+            LockSpec::Months(months) => {
+                reference_date.checked_add_months(Months::new(months as u32))
+            }
+            LockSpec::Years(years) => {
+                reference_date.checked_add_months(Months::new(years as u32 * 12))
+            }
+            LockSpec::Date(date) => Some(date),
+        }
+        .ok_or_else(|| format!("{self} is too long"))?;
+        let days = (end_date - reference_date).num_days();
+        if days <= 0 {
+            return Err(format!(
+                "{self} is not after the reference date ({reference_date})"
+            ));
+        }
+        u16::try_from(days).map_err(|_| format!("{self} is too long"))
+    }
+}
+impl core::fmt::Display for LockSpec {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            LockSpec::Days(n) => write!(f, "{n}d"),
+            LockSpec::Weeks(n) => write!(f, "{n}w"),
+            LockSpec::Months(n) => write!(f, "{n}m"),
+            LockSpec::Years(n) => write!(f, "{n}y"),
+            LockSpec::Date(date) => write!(f, "{date}"),
+        }
+    }
+}
+impl core::str::FromStr for LockSpec {
+    type Err = String;
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        if let Ok(date) = s.parse::<NaiveDate>() {
+            return Ok(LockSpec::Date(date));
+        }
+        let (value, unit) = match s.char_indices().last() {
+            Some((i, c)) if c.is_ascii_alphabetic() => (&s[..i], Some(c.to_ascii_lowercase())),
+            _ => (s, None),
+        };
+        let value = value
+            .parse::<u16>()
+            .map_err(|e| format!("Invalid lock duration {s} ({e})"))?;
+        match unit {
+            None | Some('d') => Ok(LockSpec::Days(value)),
+            Some('w') => Ok(LockSpec::Weeks(value)),
+            Some('m') => Ok(LockSpec::Months(value)),
+            Some('y') => Ok(LockSpec::Years(value)),
+            Some(unit) => Err(format!(
+                "Invalid lock duration unit {unit}, expected d, w, m or y"
+            )),
+        }
+    }
+}
+impl TryFrom<String> for LockSpec {
+    type Error = String;
+    fn try_from(value: String) -> core::result::Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+impl From<LockSpec> for String {
+    fn from(value: LockSpec) -> Self {
+        value.to_string()
+    }
+}
+
+/// Resolve the locks of the Heirs into durations in days from the reference date
+fn resolve_locks<T>(
+    heirs: Vec<(T, LockSpec)>,
+    reference_date: NaiveDate,
+) -> Result<Vec<(T, Days)>> {
+    heirs
+        .into_iter()
+        .map(|(heir, lock)| {
+            let days = lock.resolve(reference_date).map_err(Error::Generic)?;
+            Ok((heir, Days::from(days)))
+        })
+        .collect()
+}
+
+/// Verify that every Heir has a distinct lock, so that they are strictly ordered,
+/// and that every lock exceeds the minimum lock time
+fn validate_locks(mut heirs: Vec<(String, u16)>, minimum_lock_time: u16) -> Result<()> {
+    heirs.sort_by_key(|(_, days)| *days);
+    for (heir, days) in heirs.iter() {
+        if *days <= minimum_lock_time {
+            return Err(Error::Generic(format!(
+                "The lock of {heir} ({days} days) must exceed \
+                the minimum lock time ({minimum_lock_time} days)"
+            )));
+        }
+    }
+    for pair in heirs.windows(2) {
+        if pair[0].1 == pair[1].1 {
+            return Err(Error::Generic(format!(
+                "{} and {} have the same lock ({} days), Heirs must be strictly ordered",
+                pair[0].0, pair[1].0, pair[0].1
+            )));
+        }
+    }
+    Ok(())
+}
+
+impl ManualSpec {
+    /// Complete the [ManualSpec], if any, with the Heirs and minimum lock time of the template, if any
+    fn with_template(
+        manual_spec: Option<ManualSpec>,
+        template: Option<String>,
+        db: &Database,
+    ) -> Result<Option<ManualSpec>> {
+        let Some(template) = template else {
+            return Ok(manual_spec);
+        };
+        let template = HeritageConfigTemplate::load(db, &template)?;
+        let mut manual_spec = manual_spec.unwrap_or(ManualSpec {
+            version: HeritageConfigVersion::V1,
+            reference_date: None,
+            minimum_lock_time: None,
+            local_heir: vec![],
+            service_heir: vec![],
+            heir_config: vec![],
+        });
+        if manual_spec.minimum_lock_time.is_none() {
+            manual_spec.minimum_lock_time = template.minimum_lock_time;
+        }
+        manual_spec.local_heir.extend(
+            template
+                .local_heirs
+                .into_iter()
+                .map(|th| (th.heir, th.time_lock)),
+        );
+        manual_spec.service_heir.extend(
+            template
+                .service_heirs
+                .into_iter()
+                .map(|th| (th.heir, th.time_lock)),
+        );
+        manual_spec.heir_config.extend(
+            template
+                .heir_configs
+                .into_iter()
+                .map(|th| (th.heir, th.time_lock)),
+        );
+        Ok(Some(manual_spec))
+    }
+}
+
+/// Convert a native TOML date, which must not have a time, into a [NaiveDate]
+fn toml_date(datetime: &toml::value::Datetime) -> core::result::Result<NaiveDate, String> {
+    match datetime {
+        toml::value::Datetime {
+            date: Some(date),
+            time: None,
+            offset: None,
+        } => NaiveDate::from_ymd_opt(date.year as i32, date.month as u32, date.day as u32),
+        _ => None,
+    }
+    .ok_or_else(|| format!("Invalid date {datetime}, expected a date without time"))
+}
+
+/// A date in an Heritage Configuration file, either a native TOML date or a string
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(untagged)]
+enum FileDate {
+    Toml(toml::value::Datetime),
+    Text(String),
+}
+impl FileDate {
+    fn to_naive_date(&self) -> core::result::Result<NaiveDate, String> {
+        match self {
+            FileDate::Toml(datetime) => toml_date(datetime),
+            FileDate::Text(date) => date
+                .parse::<NaiveDate>()
+                .map_err(|e| format!("Invalid date {date} ({e})")),
+        }
+    }
+}
+
+/// A lock in an Heritage Configuration file, either a number of days, a native TOML date
+/// or a [LockSpec] string
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(untagged)]
+enum FileLock {
+    Days(u16),
+    Date(toml::value::Datetime),
+    Spec(String),
+}
+impl FileLock {
+    fn to_lock_spec(&self) -> core::result::Result<LockSpec, String> {
+        match self {
+            FileLock::Days(days) => Ok(LockSpec::Days(*days)),
+            FileLock::Date(datetime) => Ok(LockSpec::Date(toml_date(datetime)?)),
+            FileLock::Spec(lock) => lock.parse(),
+        }
+    }
+}
+
+/// An Heir in an Heritage Configuration file, referenced by exactly one of
+/// its local name, its service name or its raw Heir Config
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileHeir {
+    local: Option<String>,
+    service: Option<String>,
+    heir_config: Option<String>,
+    lock: FileLock,
+}
+
+/// A human-authored Heritage Configuration file, for example in YAML:
+/// ```yaml
+/// # Optional, defaults to today and 30 days
+/// reference_date: 2025-01-01
+/// minimum_lock_time: 30
+/// heirs:
+///   - local: backup
+///     lock: 1y
+///   - service: wife
+///     lock: 2030-06-01
+///   - heir_config: "xpub:[73c5da0a/86'/0'/1751476594']xpub..."
+///     lock: 1100
+/// ```
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HeritageConfigFile {
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    reference_date: Option<FileDate>,
+    #[serde(default)]
+    minimum_lock_time: Option<u16>,
+    heirs: Vec<FileHeir>,
+}
+
+impl ManualSpec {
+    /// Compile an Heritage Configuration file into a [ManualSpec]
+    fn from_file(path: &Path) -> Result<ManualSpec> {
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            log::error!("Could not read {}: {e}", path.display());
+            Error::generic(e)
+        })?;
+        let file: HeritageConfigFile = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml" | "yml") => serde_yaml::from_str(&content).map_err(Error::generic)?,
+            Some("toml") => toml::from_str(&content).map_err(Error::generic)?,
+            _ => {
+                return Err(Error::Generic(format!(
+                    "Unknown format for {}, the extension must be .yaml, .yml or .toml",
+                    path.display()
+                )))
+            }
+        };
+
+        let version = match file.version.as_deref() {
+            None | Some("v1") => HeritageConfigVersion::V1,
+            Some(version) => {
+                return Err(Error::Generic(format!(
+                    "Unsupported Heritage Configuration version {version} (only v1 exist for now)"
+                )))
+            }
+        };
+        let mut manual_spec = ManualSpec {
+            version,
+            reference_date: file
+                .reference_date
+                .map(|date| date.to_naive_date())
+                .transpose()
+                .map_err(Error::Generic)?,
+            minimum_lock_time: file.minimum_lock_time.map(Days::from),
+            local_heir: vec![],
+            service_heir: vec![],
+            heir_config: vec![],
+        };
+        for (i, heir) in file.heirs.into_iter().enumerate() {
+            let lock = heir.lock.to_lock_spec().map_err(Error::Generic)?;
+            match (heir.local, heir.service, heir.heir_config) {
+                (Some(local), None, None) => manual_spec.local_heir.push((local, lock)),
+                (None, Some(service), None) => manual_spec.service_heir.push((service, lock)),
+                (None, None, Some(heir_config)) => manual_spec.heir_config.push((
+                    parse_heir_config_value(&heir_config.split(':').collect::<Vec<_>>())
+                        .map_err(Error::Generic)?,
+                    lock,
+                )),
+                _ => {
+                    return Err(Error::Generic(format!(
+                    "Heir #{} must have exactly one of \"local\", \"service\" or \"heir_config\"",
+                    i + 1
+                )))
+                }
+            }
+        }
+        Ok(manual_spec)
+    }
 }
 
 impl super::CommandExecutor for WalletHeritageConfigSubcmd {
@@ -79,154 +480,719 @@ impl super::CommandExecutor for WalletHeritageConfigSubcmd {
                     Box::new(())
                 }
             }
-            WalletHeritageConfigSubcmd::Set { manual_spec, json } => {
-                let hc = if let Some(manual_spec) = manual_spec {
-                    let ManualSpec {
-                        version,
-                        reference_date,
-                        minimum_lock_time,
-                        local_heir,
-                        service_heir,
-                        heir_config,
-                    } = manual_spec;
-                    match version {
-                        HeritageConfigVersion::V1 => {
-                            let hcb = HeritageConfig::builder_v1();
-
-                            let hcb = if let Some(minimum_lock_time) = minimum_lock_time {
-                                hcb.minimum_lock_time(minimum_lock_time.as_u16())
-                            } else {
-                                hcb
-                            };
-
-                            let hcb = if let Some(reference_date) = reference_date {
-                                let timestamp = reference_date
-                                    .and_time(
-                                        NaiveTime::from_num_seconds_from_midnight_opt(12 * 3600, 0)
-                                            .unwrap(),
-                                    )
-                                    .and_utc()
-                                    .timestamp();
-                                hcb.reference_time(timestamp as u64)
-                            } else {
-                                hcb
-                            };
-
-                            let mut local_heirs_index: HashMap<String, HeirConfig> =
-                                if local_heir.len() > 0 {
-                                    Heir::all_in_db(&db)?
-                                        .into_iter()
-                                        .map(|h| (h.name, h.heir_config))
-                                        .collect()
-                                } else {
-                                    Default::default()
-                                };
-
-                            let mut service_heirs_index: HashMap<String, HeirConfig> =
-                                if service_heir.len() > 0 {
-                                    if let AnyOnlineWallet::Service(sb) = wallet.online_wallet() {
-                                        sb.service_client()
-                                            .ok_or(Error::UninitializedServiceClient)?
-                                            .list_heirs()
-                                            .await?
-                                            .into_iter()
-                                            .map(|h| (h.display_name, h.heir_config))
-                                            .collect()
-                                    } else {
-                                        return Err(Error::IncorrectOnlineWallet("service"));
-                                    }
-                                } else {
-                                    Default::default()
-                                };
-
-                            let hcb = hcb
-                                .expand_heritages(
-                                    local_heir
-                                        .into_iter()
-                                        .map(|(local_heir_name, time_lock)| {
-                                            let heir_config = local_heirs_index
-                                                .remove(&local_heir_name)
-                                                .ok_or_else(|| {
-                                                    Error::Generic(format!(
-                                                        "{local_heir_name} \
-                                                does not exist or was \
-                                                specfied multiple times"
-                                                    ))
-                                                })?;
-                                            Ok(Heritage {
-                                                heir_config,
-                                                time_lock,
-                                            })
-                                        })
-                                        .collect::<Result<Vec<_>>>()?,
-                                )
-                                .expand_heritages(
-                                    service_heir
-                                        .into_iter()
-                                        .map(|(service_heir_name, time_lock)| {
-                                            let heir_config = service_heirs_index
-                                                .remove(&service_heir_name)
-                                                .ok_or_else(|| {
-                                                    Error::Generic(format!(
-                                                        "{service_heir_name} \
-                                                does not exist or was \
-                                                specified multiple times"
-                                                    ))
-                                                })?;
-                                            Ok(Heritage {
-                                                heir_config,
-                                                time_lock,
-                                            })
-                                        })
-                                        .collect::<Result<Vec<_>>>()?,
-                                )
-                                .expand_heritages(heir_config.into_iter().map(
-                                    |(heir_config, time_lock)| Heritage {
-                                        heir_config,
-                                        time_lock,
-                                    },
-                                ));
-
-                            hcb.build()
-                        }
-                    }
-                } else if let Some(hc) = json {
-                    hc
-                } else {
-                    unreachable!("either manual_spec or json must be present")
+            WalletHeritageConfigSubcmd::Timeline => {
+                Box::new(HeritageTimeline::compute(&wallet, &db).await?)
+            }
+            WalletHeritageConfigSubcmd::Set {
+                json,
+                template,
+                file,
+                manual_spec,
+                dry_run,
+                skip_confirmation,
+            } => {
+                let manual_spec = match file {
+                    Some(file) => Some(ManualSpec::from_file(&file)?),
+                    None => ManualSpec::with_template(manual_spec, template, &db)?,
                 };
+                let hc = build_heritage_config(json, manual_spec, &wallet, &db).await?;
+                let diff = HeritageConfigDiff::compute(&wallet, &db, &hc).await?;
+                if dry_run {
+                    return Ok(Box::new(diff));
+                }
+                if !skip_confirmation {
+                    interactive_println(&diff.text());
+                    if !ask_user_confirmation("Do you want to set this Heritage Configuration?")
+                        .await?
+                    {
+                        return Ok(Box::new(Unsuccessful::cancelled(
+                            "Heritage Configuration not set",
+                        )));
+                    }
+                }
                 Box::new(wallet.set_heritage_config(hc).await?)
             }
+            WalletHeritageConfigSubcmd::Diff {
+                json,
+                template,
+                file,
+                manual_spec,
+            } => {
+                let manual_spec = match file {
+                    Some(file) => Some(ManualSpec::from_file(&file)?),
+                    None => ManualSpec::with_template(manual_spec, template, &db)?,
+                };
+                let hc = build_heritage_config(json, manual_spec, &wallet, &db).await?;
+                Box::new(HeritageConfigDiff::compute(&wallet, &db, &hc).await?)
+            }
+            WalletHeritageConfigSubcmd::Renew {
+                consolidate,
+                fee_rate,
+                sign,
+                broadcast,
+                dry_run,
+                skip_confirmation,
+                psbt_output,
+            } => {
+                let mut hc_list = wallet.list_subwallet_configs().await?;
+                if hc_list.is_empty() {
+                    return Err(Error::Generic(
+                        "The wallet has no Heritage Configuration to renew".to_owned(),
+                    ));
+                }
+                let current = HeritageConfigV1View::new(&hc_list.remove(0).heritage_config)?;
+                let hc = HeritageConfig::builder_v1()
+                    .minimum_lock_time(current.minimum_lock_time.as_u16())
+                    .reference_time(reference_timestamp(Utc::now().date_naive()))
+                    .expand_heritages(current.heritages)
+                    .build();
+                let diff = HeritageConfigDiff::compute(&wallet, &db, &hc).await?;
+                if dry_run {
+                    return Ok(Box::new(diff));
+                }
+                if !skip_confirmation {
+                    interactive_println(&diff.text());
+                    if !ask_user_confirmation("Do you want to renew the Heritage Configuration?")
+                        .await?
+                    {
+                        return Ok(Box::new(Unsuccessful::cancelled(
+                            "Heritage Configuration not renewed",
+                        )));
+                    }
+                }
+                let renewed = wallet.set_heritage_config(hc.clone()).await?;
+                if !consolidate {
+                    return Ok(Box::new(renewed));
+                }
+
+                // Frozen UTXOs are left where they are
+                let frozen = WalletLabels::load_or_default(&db, wallet.name())?.frozen;
+                let obsolete_outpoints = wallet
+                    .online_wallet()
+                    .list_heritage_utxos()
+                    .await?
+                    .into_iter()
+                    .filter(|utxo| utxo.heritage_config != hc && !frozen.contains(&utxo.outpoint))
+                    .map(|utxo| utxo.outpoint)
+                    .collect::<Vec<_>>();
+                if obsolete_outpoints.is_empty() {
+                    return Ok(Box::new(
+                        "Heritage Configuration renewed, no coins to consolidate",
+                    ));
+                }
+                log::info!(
+                    "Consolidating {} UTXO(s) from obsolete Heritage Configurations",
+                    obsolete_outpoints.len()
+                );
+                let drain_to =
+                    wallet_address_to_string(&wallet.online_wallet().get_address().await?);
+                let (psbt, summary) = wallet
+                    .create_psbt(NewTx {
+                        spending_config: NewTxSpendingConfig::DrainTo(NewTxDrainTo { drain_to }),
+                        fee_policy: fee_rate.map(|rate| NewTxFeePolicy::Rate { rate }),
+                        utxo_selection: Some(NewTxUtxoSelection::UseOnly {
+                            use_only: obsolete_outpoints,
+                        }),
+                        disable_rbf: None,
+                    })
+                    .await?;
+                SpendFlow::new(psbt, bitcoin_network::get())
+                    .transaction_summary(&summary)
+                    .fingerprints(&get_fingerprints(&db).await?)
+                    .contacts(&get_contacts(&db)?)
+                    .labels(&get_labels(&db, wallet.name())?)
+                    .current_fee_rate(current_fee_rate(&wallet).await)
+                    .display()
+                    .set_sign(if sign {
+                        Some(wallet.key_provider())
+                    } else {
+                        None
+                    })
+                    .set_broadcast(if broadcast {
+                        Some(wallet.online_wallet())
+                    } else {
+                        None
+                    })
+                    .set_skip_confirmations(skip_confirmation)
+                    .psbt_output(&psbt_output)
+                    .run()
+                    .await?
+            }
         };
         Ok(res)
     }
 }
 
+/// Build the [HeritageConfig] specified either as a JSON or manually
+async fn build_heritage_config(
+    json: Option<HeritageConfig>,
+    manual_spec: Option<ManualSpec>,
+    wallet: &Wallet,
+    db: &Database,
+) -> Result<HeritageConfig> {
+    let hc = if let Some(manual_spec) = manual_spec {
+        let ManualSpec {
+            version,
+            reference_date,
+            minimum_lock_time,
+            local_heir,
+            service_heir,
+            heir_config,
+        } = manual_spec;
+        let resolution_date = reference_date.unwrap_or_else(|| Utc::now().date_naive());
+        match version {
+            HeritageConfigVersion::V1 => {
+                // Relative and absolute locks are computed against the reference date
+                let local_heir = resolve_locks(local_heir, resolution_date)?;
+                let service_heir = resolve_locks(service_heir, resolution_date)?;
+                let heir_config = resolve_locks(heir_config, resolution_date)?;
+                // The locks are validated once the Heritage Configuration is built, against its
+                // minimum lock time which defaults to the one of the library
+                let locks = local_heir
+                    .iter()
+                    .chain(service_heir.iter())
+                    .map(|(name, days)| (name.clone(), days.as_u16()))
+                    .chain(heir_config.iter().map(|(heir_config, days)| {
+                        (
+                            serde_json::to_string(heir_config)
+                                .expect("Caller responsability to ensure Json serialization works"),
+                            days.as_u16(),
+                        )
+                    }))
+                    .collect();
+
+                let hcb = HeritageConfig::builder_v1();
+
+                let hcb = if let Some(minimum_lock_time) = minimum_lock_time {
+                    hcb.minimum_lock_time(minimum_lock_time.as_u16())
+                } else {
+                    hcb
+                };
+
+                let hcb = if let Some(reference_date) = reference_date {
+                    hcb.reference_time(reference_timestamp(reference_date))
+                } else {
+                    hcb
+                };
+
+                let mut local_heirs_index: HashMap<String, HeirConfig> = if local_heir.len() > 0 {
+                    Heir::all_in_db(db)?
+                        .into_iter()
+                        .map(|h| (h.name, h.heir_config))
+                        .collect()
+                } else {
+                    Default::default()
+                };
+
+                let mut service_heirs_index: HashMap<String, HeirConfig> = if service_heir.len() > 0
+                {
+                    if let AnyOnlineWallet::Service(sb) = wallet.online_wallet() {
+                        sb.service_client()
+                            .ok_or(Error::UninitializedServiceClient)?
+                            .list_heirs()
+                            .await?
+                            .into_iter()
+                            .map(|h| (h.display_name, h.heir_config))
+                            .collect()
+                    } else {
+                        return Err(Error::IncorrectOnlineWallet("service"));
+                    }
+                } else {
+                    Default::default()
+                };
+
+                let hcb = hcb
+                    .expand_heritages(
+                        local_heir
+                            .into_iter()
+                            .map(|(local_heir_name, time_lock)| {
+                                let heir_config = local_heirs_index
+                                    .remove(&local_heir_name)
+                                    .ok_or_else(|| {
+                                        Error::Generic(format!(
+                                            "{local_heir_name} \
+                                    does not exist or was \
+                                    specfied multiple times"
+                                        ))
+                                    })?;
+                                Ok(Heritage {
+                                    heir_config,
+                                    time_lock,
+                                })
+                            })
+                            .collect::<Result<Vec<_>>>()?,
+                    )
+                    .expand_heritages(
+                        service_heir
+                            .into_iter()
+                            .map(|(service_heir_name, time_lock)| {
+                                let heir_config = service_heirs_index
+                                    .remove(&service_heir_name)
+                                    .ok_or_else(|| {
+                                        Error::Generic(format!(
+                                            "{service_heir_name} \
+                                    does not exist or was \
+                                    specified multiple times"
+                                        ))
+                                    })?;
+                                Ok(Heritage {
+                                    heir_config,
+                                    time_lock,
+                                })
+                            })
+                            .collect::<Result<Vec<_>>>()?,
+                    )
+                    .expand_heritages(heir_config.into_iter().map(|(heir_config, time_lock)| {
+                        Heritage {
+                            heir_config,
+                            time_lock,
+                        }
+                    }));
+
+                let hc = hcb.build();
+                validate_locks(
+                    locks,
+                    HeritageConfigV1View::new(&hc)?.minimum_lock_time.as_u16(),
+                )?;
+                hc
+            }
+        }
+    } else if let Some(hc) = json {
+        hc
+    } else {
+        unreachable!("either manual_spec or json must be present")
+    };
+    Ok(hc)
+}
+
+/// The timestamp of the given date at noon (UTC), used as the reference time of Heritage Configurations
+fn reference_timestamp(date: NaiveDate) -> u64 {
+    date.and_time(NaiveTime::from_num_seconds_from_midnight_opt(12 * 3600, 0).unwrap())
+        .and_utc()
+        .timestamp() as u64
+}
+
+/// The calendar date (UTC) of a timestamp
+pub(crate) fn format_date(ts: u64) -> String {
+    DateTime::from_timestamp(ts as i64, 0)
+        .expect("timestamp is in range")
+        .date_naive()
+        .to_string()
+}
+
+/// The names under which the Heirs are known locally or by the service,
+/// used to present Heritage Configurations with names instead of raw keys
+pub(crate) async fn heir_names(
+    wallet: &Wallet,
+    db: &Database,
+) -> Result<Vec<(HeirConfig, String)>> {
+    let mut names = Heir::all_in_db(db)?
+        .into_iter()
+        .map(|h| (h.heir_config, h.name))
+        .collect::<Vec<_>>();
+    if let AnyOnlineWallet::Service(sb) = wallet.online_wallet() {
+        if let Some(service_client) = sb.service_client() {
+            names.extend(
+                service_client
+                    .list_heirs()
+                    .await?
+                    .into_iter()
+                    .map(|h| (h.heir_config, h.display_name)),
+            );
+        }
+    }
+    Ok(names)
+}
+
+/// The name of the Heir with the given [HeirConfig], or its JSON representation if unknown
+pub(crate) fn heir_name(names: &[(HeirConfig, String)], heir_config: &HeirConfig) -> String {
+    names
+        .iter()
+        .find(|(hc, _)| hc == heir_config)
+        .map(|(_, name)| name.clone())
+        .unwrap_or_else(|| {
+            serde_json::to_string(heir_config)
+                .expect("Caller responsability to ensure Json serialization works")
+        })
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct HeirLock {
+    heir: String,
+    lock_days: u16,
+    maturity_date: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct HeirLockChange {
+    heir: String,
+    current: HeirLock,
+    new: HeirLock,
+}
+
+/// The differences between the current Heritage Configuration of a wallet and a new one
+#[derive(Debug, Clone, serde::Serialize)]
+struct HeritageConfigDiff {
+    current_reference_date: Option<String>,
+    new_reference_date: String,
+    current_minimum_lock_time: Option<u16>,
+    new_minimum_lock_time: u16,
+    added_heirs: Vec<HeirLock>,
+    removed_heirs: Vec<HeirLock>,
+    changed_heirs: Vec<HeirLockChange>,
+    unchanged_heirs: Vec<HeirLock>,
+}
+impl HeritageConfigDiff {
+    async fn compute(wallet: &Wallet, db: &Database, new: &HeritageConfig) -> Result<Self> {
+        let current = wallet
+            .list_subwallet_configs()
+            .await?
+            .into_iter()
+            .next()
+            .map(|meta| HeritageConfigV1View::new(&meta.heritage_config))
+            .transpose()?;
+        let new = HeritageConfigV1View::new(new)?;
+        let names = heir_names(wallet, db).await?;
+
+        let heir_lock = |view: &HeritageConfigV1View, heritage: &Heritage| HeirLock {
+            heir: heir_name(&names, &heritage.heir_config),
+            lock_days: heritage.time_lock.as_u16(),
+            maturity_date: format_date(view.maturity_ts(heritage)),
+        };
+
+        let mut added_heirs = vec![];
+        let mut changed_heirs = vec![];
+        let mut unchanged_heirs = vec![];
+        for heritage in new.heritages.iter() {
+            let current_heritage = current.as_ref().and_then(|current| {
+                current
+                    .heritages
+                    .iter()
+                    .find(|h| h.heir_config == heritage.heir_config)
+                    .map(|h| (current, h))
+            });
+            let new_lock = heir_lock(&new, heritage);
+            match current_heritage {
+                None => added_heirs.push(new_lock),
+                Some((current, current_heritage)) => {
+                    let current_lock = heir_lock(current, current_heritage);
+                    if current_lock.lock_days == new_lock.lock_days
+                        && current_lock.maturity_date == new_lock.maturity_date
+                    {
+                        unchanged_heirs.push(new_lock)
+                    } else {
+                        changed_heirs.push(HeirLockChange {
+                            heir: new_lock.heir.clone(),
+                            current: current_lock,
+                            new: new_lock,
+                        })
+                    }
+                }
+            }
+        }
+        let removed_heirs = current
+            .as_ref()
+            .map(|current| {
+                current
+                    .heritages
+                    .iter()
+                    .filter(|h| {
+                        !new.heritages
+                            .iter()
+                            .any(|nh| nh.heir_config == h.heir_config)
+                    })
+                    .map(|h| heir_lock(current, h))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(HeritageConfigDiff {
+            current_reference_date: current.as_ref().map(|c| format_date(c.reference_time)),
+            new_reference_date: format_date(new.reference_time),
+            current_minimum_lock_time: current.as_ref().map(|c| c.minimum_lock_time.as_u16()),
+            new_minimum_lock_time: new.minimum_lock_time.as_u16(),
+            added_heirs,
+            removed_heirs,
+            changed_heirs,
+            unchanged_heirs,
+        })
+    }
+}
+impl Displayable for HeritageConfigDiff {
+    fn text(&self) -> String {
+        fn change<T: core::fmt::Display + PartialEq>(current: Option<&T>, new: &T) -> String {
+            match current {
+                Some(current) if current == new => format!("{new} (unchanged)"),
+                Some(current) => format!("{current} -> {new}"),
+                None => format!("{new} (no current Heritage Configuration)"),
+            }
+        }
+        let mut lines = vec![
+            format!(
+                "\x1b[1mReference date\x1b[0m: {}",
+                change(
+                    self.current_reference_date.as_ref(),
+                    &self.new_reference_date
+                )
+            ),
+            format!(
+                "\x1b[1mMinimum lock time\x1b[0m: {} days",
+                change(
+                    self.current_minimum_lock_time.as_ref(),
+                    &self.new_minimum_lock_time
+                )
+            ),
+        ];
+        for hl in self.added_heirs.iter() {
+            lines.push(format!(
+                "\x1b[32m+ {}: {} days ({})\x1b[0m",
+                hl.heir, hl.lock_days, hl.maturity_date
+            ));
+        }
+        for hl in self.removed_heirs.iter() {
+            lines.push(format!(
+                "\x1b[31m- {}: {} days ({})\x1b[0m",
+                hl.heir, hl.lock_days, hl.maturity_date
+            ));
+        }
+        for hlc in self.changed_heirs.iter() {
+            lines.push(format!(
+                "\x1b[33m~ {}: {} days ({}) -> {} days ({})\x1b[0m",
+                hlc.heir,
+                hlc.current.lock_days,
+                hlc.current.maturity_date,
+                hlc.new.lock_days,
+                hlc.new.maturity_date
+            ));
+        }
+        for hl in self.unchanged_heirs.iter() {
+            lines.push(format!(
+                "= {}: {} days ({})",
+                hl.heir, hl.lock_days, hl.maturity_date
+            ));
+        }
+        lines.join("\n")
+    }
+    fn json(&self) -> serde_json::Value {
+        serde_json::to_value(self)
+            .expect("Caller responsability to ensure Json serialization works")
+    }
+    fn table(&self) -> String {
+        self.text()
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct ConfigTimeline {
+    current: bool,
+    reference_date: String,
+    minimum_lock_time: u16,
+    utxo_count: usize,
+    /// The amount locked by this Heritage Configuration, in sat
+    amount: u64,
+    heirs: Vec<HeirLock>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct TimelineEvent {
+    date: String,
+    heir: String,
+    /// The amount the Heir can spend from that date, in sat
+    amount: u64,
+    matured: bool,
+    #[serde(skip)]
+    ts: u64,
+}
+
+/// The Heritage Configurations of a wallet, and the dates at which each Heir can spend which coins
+#[derive(Debug, Clone, serde::Serialize)]
+struct HeritageTimeline {
+    configs: Vec<ConfigTimeline>,
+    events: Vec<TimelineEvent>,
+}
+impl HeritageTimeline {
+    async fn compute(wallet: &Wallet, db: &Database) -> Result<Self> {
+        let subwallet_configs = wallet.list_subwallet_configs().await?;
+        let utxos = wallet.online_wallet().list_heritage_utxos().await?;
+        let names = heir_names(wallet, db).await?;
+        let now = timestamp_now();
+
+        let mut configs = vec![];
+        let mut events = vec![];
+        for (i, meta) in subwallet_configs.iter().enumerate() {
+            let view = HeritageConfigV1View::new(&meta.heritage_config)?;
+            let (utxo_count, amount) = utxos
+                .iter()
+                .filter(|utxo| utxo.heritage_config == meta.heritage_config)
+                .fold((0, 0), |(count, amount), utxo| {
+                    (count + 1, amount + utxo.amount.to_sat())
+                });
+            let mut heirs = vec![];
+            for heritage in view.heritages.iter() {
+                let heir = heir_name(&names, &heritage.heir_config);
+                let ts = view.maturity_ts(heritage);
+                if utxo_count > 0 {
+                    events.push(TimelineEvent {
+                        date: format_date(ts),
+                        heir: heir.clone(),
+                        amount,
+                        matured: ts <= now,
+                        ts,
+                    });
+                }
+                heirs.push(HeirLock {
+                    heir,
+                    lock_days: heritage.time_lock.as_u16(),
+                    maturity_date: format_date(ts),
+                });
+            }
+            configs.push(ConfigTimeline {
+                current: i == 0,
+                reference_date: format_date(view.reference_time),
+                minimum_lock_time: view.minimum_lock_time.as_u16(),
+                utxo_count,
+                amount,
+                heirs,
+            });
+        }
+        events.sort_by_key(|e| e.ts);
+        Ok(HeritageTimeline { configs, events })
+    }
+}
+impl Displayable for HeritageTimeline {
+    fn text(&self) -> String {
+        let btc = |sat: u64| {
+            format!(
+                "{} BTC",
+                Amount::from_sat(sat).display_in(Denomination::Bitcoin)
+            )
+        };
+        let mut lines = vec!["\x1b[1mHeritage Configurations\x1b[0m".to_owned()];
+        for config in self.configs.iter() {
+            let coins = if config.utxo_count > 0 {
+                format!("{} UTXO(s), {}", config.utxo_count, btc(config.amount))
+            } else {
+                "no coins".to_owned()
+            };
+            lines.push(format!(
+                "  {}reference date {}, minimum lock time {} days, {coins}",
+                if config.current { "[current] " } else { "" },
+                config.reference_date,
+                config.minimum_lock_time,
+            ));
+            for hl in config.heirs.iter() {
+                lines.push(format!(
+                    "    {}: {} days -> {}",
+                    hl.heir, hl.lock_days, hl.maturity_date
+                ));
+            }
+        }
+        lines.push("\x1b[1mWho can spend what, and when\x1b[0m".to_owned());
+        if self.events.is_empty() {
+            lines.push("  No coins can be inherited".to_owned());
+        }
+        for event in self.events.iter() {
+            let line = format!(
+                "  {}  {} can spend {}",
+                event.date,
+                event.heir,
+                btc(event.amount)
+            );
+            // Highlight the coins an Heir can already spend
+            lines.push(if event.matured {
+                format!("\x1b[31m{line} (already possible)\x1b[0m")
+            } else {
+                line
+            });
+        }
+        lines.join("\n")
+    }
+    fn json(&self) -> serde_json::Value {
+        serde_json::to_value(self)
+            .expect("Caller responsability to ensure Json serialization works")
+    }
+    fn table(&self) -> String {
+        self.text()
+    }
+}
+
+/// A flattened view of a v1 Heritage Configuration, built from its serialized form
+#[derive(Debug, Clone, serde::Deserialize)]
+pub(crate) struct HeritageConfigV1View {
+    pub heritages: Vec<Heritage>,
+    pub reference_time: u64,
+    pub minimum_lock_time: Days,
+}
+impl HeritageConfigV1View {
+    pub fn new(heritage_config: &HeritageConfig) -> Result<Self> {
+        // Only v1 exists for now
+        Ok(serde_json::from_value(serde_json::to_value(
+            heritage_config,
+        )?)?)
+    }
+    /// The timestamp after which the Heir of the given [Heritage] can spend the coins
+    pub fn maturity_ts(&self, heritage: &Heritage) -> u64 {
+        self.reference_time + heritage.time_lock.as_u16() as u64 * 86400
+    }
+    /// The timestamp after which the first Heir can spend the coins, if any
+    pub fn first_maturity_ts(&self) -> Option<u64> {
+        self.heritages.iter().map(|h| self.maturity_ts(h)).min()
+    }
+}
+
 fn parse_heritage_configuration(
     val: &str,
 ) -> core::result::Result<HeritageConfig, serde_json::Error> {
     serde_json::from_str(val)
 }
 
-fn parse_days(val: &str) -> core::result::Result<Days, String> {
+pub(super) fn parse_days(val: &str) -> core::result::Result<Days, String> {
     Ok(val
         .parse::<Days>()
         .map_err(|e| format!("Could not parse as Days ({e})"))?)
 }
 
-fn parse_heir(val: &str) -> core::result::Result<(String, Days), String> {
+pub(super) fn parse_heir(val: &str) -> core::result::Result<(String, LockSpec), String> {
+    if let Some((name, date)) = val.split_once('@') {
+        let date = date
+            .parse::<NaiveDate>()
+            .map_err(|e| format!("Invalid date {date} ({e})"))?;
+        return Ok((name.to_owned(), LockSpec::Date(date)));
+    }
     let splits = val.split(':').collect::<Vec<_>>();
     match splits.len() {
-        2 => Ok((splits[0].to_owned(), parse_days(splits[1])?)),
+        2 => Ok((splits[0].to_owned(), splits[1].parse()?)),
         _ => Err(format!("Invalid number of parts: {}", splits.len())),
     }
 }
 
-fn parse_heir_config(val: &str) -> core::result::Result<(HeirConfig, Days), String> {
-    let splits = val.split(':').collect::<Vec<_>>();
-    let hc = match splits.len() {
-        2 => HeirConfig::HeirXPubkey(AccountXPub::try_from(splits[0]).map_err(|e| e.to_string())?),
-        3 => match splits[0] {
+pub(super) fn parse_heir_config(val: &str) -> core::result::Result<(HeirConfig, LockSpec), String> {
+    // Absolute dates are specified with <KIND>:<VALUE>@<DATE>, with the same number
+    // of parts as relative locks once the date is put aside
+    let (splits, lock) = if let Some((heir_config, date)) = val.split_once('@') {
+        let date = date
+            .parse::<NaiveDate>()
+            .map_err(|e| format!("Invalid date {date} ({e})"))?;
+        let mut splits = heir_config.split(':').collect::<Vec<_>>();
+        splits.push("");
+        (splits, LockSpec::Date(date))
+    } else {
+        let splits = val.split(':').collect::<Vec<_>>();
+        let lock = splits[splits.len() - 1].parse()?;
+        (splits, lock)
+    };
+    if splits.len() < 2 {
+        return Err(format!("Invalid number of parts: {}", splits.len()));
+    }
+    let hc = parse_heir_config_value(&splits[..splits.len() - 1])?;
+    Ok((hc, lock))
+}
+
+/// Parse the <KIND>:<VALUE> or <VALUE> parts of an Heir Config
+fn parse_heir_config_value(splits: &[&str]) -> core::result::Result<HeirConfig, String> {
+    Ok(match splits.len() {
+        1 => HeirConfig::HeirXPubkey(AccountXPub::try_from(splits[0]).map_err(|e| e.to_string())?),
+        2 => match splits[0] {
             "xpub" => HeirConfig::HeirXPubkey(
                 AccountXPub::try_from(splits[1]).map_err(|e| e.to_string())?,
             ),
@@ -235,7 +1201,151 @@ fn parse_heir_config(val: &str) -> core::result::Result<(HeirConfig, Days), Stri
             // ),
             _ => return Err(format!("Invalid KIND: {}", splits[0])),
         },
-        _ => return Err(format!("Invalid number of parts: {}", splits.len())),
-    };
-    Ok((hc, parse_days(splits[splits.len() - 1])?))
+        _ => return Err(format!("Invalid number of parts: {}", splits.len() + 1)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn lock_spec_parsing() {
+        assert_eq!("720".parse(), Ok(LockSpec::Days(720)));
+        assert_eq!("720d".parse(), Ok(LockSpec::Days(720)));
+        assert_eq!("6w".parse(), Ok(LockSpec::Weeks(6)));
+        assert_eq!("18M".parse(), Ok(LockSpec::Months(18)));
+        assert_eq!("2y".parse(), Ok(LockSpec::Years(2)));
+        assert_eq!("2030-06-01".parse(), Ok(LockSpec::Date(date("2030-06-01"))));
+        assert!("".parse::<LockSpec>().is_err());
+        assert!("12x".parse::<LockSpec>().is_err());
+        assert!("y".parse::<LockSpec>().is_err());
+        assert!("70000d".parse::<LockSpec>().is_err());
+    }
+
+    #[test]
+    fn lock_spec_display_round_trip() {
+        for lock in [
+            LockSpec::Days(720),
+            LockSpec::Weeks(6),
+            LockSpec::Months(18),
+            LockSpec::Years(2),
+            LockSpec::Date(date("2030-06-01")),
+        ] {
+            assert_eq!(lock.to_string().parse(), Ok(lock));
+        }
+    }
+
+    #[test]
+    fn lock_spec_resolution() {
+        let reference_date = date("2024-01-31");
+        assert_eq!(LockSpec::Days(720).resolve(reference_date), Ok(720));
+        assert_eq!(LockSpec::Weeks(2).resolve(reference_date), Ok(14));
+        // Months are calendar months, clamped to the end of the month
+        assert_eq!(LockSpec::Months(1).resolve(reference_date), Ok(29));
+        assert_eq!(LockSpec::Years(1).resolve(date("2024-01-01")), Ok(366));
+        assert_eq!(
+            LockSpec::Date(date("2024-03-01")).resolve(reference_date),
+            Ok(30)
+        );
+    }
+
+    #[test]
+    fn lock_spec_resolution_errors() {
+        let reference_date = date("2024-01-31");
+        assert!(LockSpec::Weeks(10_000).resolve(reference_date).is_err());
+        assert!(LockSpec::Years(200).resolve(reference_date).is_err());
+        assert!(LockSpec::Date(reference_date)
+            .resolve(reference_date)
+            .is_err());
+        assert!(LockSpec::Date(date("2023-12-31"))
+            .resolve(reference_date)
+            .is_err());
+    }
+
+    #[test]
+    fn toml_file_native_dates() {
+        let file: HeritageConfigFile = toml::from_str(
+            r#"
+            reference_date = 2025-01-01
+            [[heirs]]
+            local = "backup"
+            lock = "1y"
+            [[heirs]]
+            service = "wife"
+            lock = 2030-06-01
+            [[heirs]]
+            local = "brother"
+            lock = 1100
+            [[heirs]]
+            local = "sister"
+            lock = "2031-06-01"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            file.reference_date.unwrap().to_naive_date(),
+            Ok(date("2025-01-01"))
+        );
+        let locks = file
+            .heirs
+            .iter()
+            .map(|heir| heir.lock.to_lock_spec())
+            .collect::<core::result::Result<Vec<_>, _>>();
+        assert_eq!(
+            locks,
+            Ok(vec![
+                LockSpec::Years(1),
+                LockSpec::Date(date("2030-06-01")),
+                LockSpec::Days(1100),
+                LockSpec::Date(date("2031-06-01")),
+            ])
+        );
+    }
+
+    #[test]
+    fn toml_file_rejects_date_times() {
+        let file: HeritageConfigFile = toml::from_str(
+            r#"
+            reference_date = 2025-01-01T12:00:00Z
+            heirs = []
+            "#,
+        )
+        .unwrap();
+        assert!(file.reference_date.unwrap().to_naive_date().is_err());
+    }
+
+    #[test]
+    fn yaml_file_dates() {
+        let file: HeritageConfigFile = serde_yaml::from_str(
+            "reference_date: 2025-01-01\nheirs:\n  - local: backup\n    lock: 2030-06-01\n",
+        )
+        .unwrap();
+        assert_eq!(
+            file.reference_date.unwrap().to_naive_date(),
+            Ok(date("2025-01-01"))
+        );
+        assert_eq!(
+            file.heirs[0].lock.to_lock_spec(),
+            Ok(LockSpec::Date(date("2030-06-01")))
+        );
+    }
+
+    #[test]
+    fn locks_validation() {
+        let heirs = |locks: &[u16]| {
+            locks
+                .iter()
+                .enumerate()
+                .map(|(i, days)| (format!("heir{i}"), *days))
+                .collect::<Vec<_>>()
+        };
+        assert!(validate_locks(heirs(&[720, 360]), 30).is_ok());
+        assert!(validate_locks(heirs(&[720, 720]), 30).is_err());
+        assert!(validate_locks(heirs(&[720, 30]), 30).is_err());
+    }
 }
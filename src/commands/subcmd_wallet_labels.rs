@@ -0,0 +1,466 @@
+use core::{any::Any, str::FromStr};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::PathBuf,
+};
+
+use btc_heritage_wallet::{
+    bitcoin::{address::NetworkUnchecked, Address, OutPoint, Txid},
+    btc_heritage::{heritage_wallet::WalletAddress, utils::bitcoin_network},
+    errors::{Error, Result},
+    heritage_service_api_client::{HeritageUtxo, TransactionSummary},
+    Database, DatabaseItem, Wallet,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::utils::wallet_address_to_string;
+
+/// The labels of a wallet, for its transactions, addresses and UTXOs, and the frozen state
+/// of its UTXOs. It has the same name as its wallet.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WalletLabels {
+    name: String,
+    pub transactions: BTreeMap<Txid, String>,
+    pub addresses: BTreeMap<String, String>,
+    pub outputs: BTreeMap<OutPoint, String>,
+    /// The UTXOs that must not be spent unless explicitly included
+    pub frozen: BTreeSet<OutPoint>,
+}
+
+impl DatabaseItem for WalletLabels {
+    fn item_key_prefix() -> &'static str {
+        "wallet_labels#"
+    }
+    fn item_default_name_key() -> &'static str {
+        "default_wallet_labels_name"
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn rename(&mut self, new_name: String) {
+        self.name = new_name;
+    }
+}
+
+impl WalletLabels {
+    /// Load the labels of the wallet, empty ones if there are none yet
+    pub fn load_or_default(db: &Database, wallet_name: &str) -> Result<Self> {
+        if Self::list_names(db)?.iter().any(|name| name == wallet_name) {
+            Self::load(db, wallet_name)
+        } else {
+            Ok(Self {
+                name: wallet_name.to_owned(),
+                ..Default::default()
+            })
+        }
+    }
+    /// Save the labels, creating them in the database if needed
+    pub fn store(&self, db: &mut Database) -> Result<()> {
+        if self.is_stored(db)? {
+            self.save(db)
+        } else {
+            self.create(db)
+        }
+    }
+    /// Whether the labels are already in the database
+    pub fn is_stored(&self, db: &Database) -> Result<bool> {
+        Ok(Self::list_names(db)?.iter().any(|name| *name == self.name))
+    }
+
+    /// The labels as BIP329 records
+    fn to_bip329(&self) -> Vec<Bip329Record> {
+        let record = |kind: &str, reference: String, label: Option<&String>| Bip329Record {
+            kind: kind.to_owned(),
+            reference,
+            label: label.cloned(),
+            origin: None,
+            spendable: None,
+        };
+        let mut records = vec![];
+        records.extend(
+            self.transactions
+                .iter()
+                .map(|(txid, label)| record("tx", txid.to_string(), Some(label))),
+        );
+        records.extend(
+            self.addresses
+                .iter()
+                .map(|(address, label)| record("addr", address.clone(), Some(label))),
+        );
+        // Frozen UTXOs are exported even without label
+        let outpoints = self
+            .outputs
+            .keys()
+            .chain(self.frozen.iter())
+            .collect::<BTreeSet<_>>();
+        records.extend(outpoints.into_iter().map(|outpoint| Bip329Record {
+            spendable: self.frozen.contains(outpoint).then_some(false),
+            ..record("output", outpoint.to_string(), self.outputs.get(outpoint))
+        }));
+        records
+    }
+
+    /// Merge a BIP329 record in the labels, returning false if it is not applicable to a wallet
+    fn import_bip329(&mut self, record: Bip329Record) -> Result<bool> {
+        match record.kind.as_str() {
+            "tx" => {
+                let txid = Txid::from_str(&record.reference).map_err(Error::generic)?;
+                if let Some(label) = record.label {
+                    self.transactions.insert(txid, label);
+                }
+            }
+            "addr" => {
+                let address = Address::from_str(&record.reference)
+                    .map_err(Error::generic)?
+                    .require_network(bitcoin_network::get())
+                    .map_err(|e| Error::InvalidAddressNetwork(e.to_string()))?;
+                if let Some(label) = record.label {
+                    self.addresses.insert(address.to_string(), label);
+                }
+            }
+            "output" => {
+                let outpoint = OutPoint::from_str(&record.reference).map_err(Error::generic)?;
+                if let Some(label) = record.label {
+                    self.outputs.insert(outpoint, label);
+                }
+                match record.spendable {
+                    Some(false) => {
+                        self.frozen.insert(outpoint);
+                    }
+                    Some(true) => {
+                        self.frozen.remove(&outpoint);
+                    }
+                    None => (),
+                }
+            }
+            // Inputs, public keys and xpubs labels have no use in an Heritage wallet
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// A label record of the BIP329 format
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bip329Record {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(rename = "ref")]
+    reference: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    origin: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    spendable: Option<bool>,
+}
+impl crate::display::SerdeDisplay for Bip329Record {}
+
+/// The addresses of a wallet, with their label
+pub struct LabeledAddresses(Vec<(WalletAddress, Option<String>)>);
+
+impl LabeledAddresses {
+    pub fn new(addresses: Vec<WalletAddress>, labels: &WalletLabels) -> Self {
+        Self(
+            addresses
+                .into_iter()
+                .map(|address| {
+                    let label = labels
+                        .addresses
+                        .get(&wallet_address_to_string(&address))
+                        .cloned();
+                    (address, label)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl crate::display::Displayable for LabeledAddresses {
+    fn text(&self) -> String {
+        self.0
+            .iter()
+            .map(|(address, label)| match label {
+                Some(label) => format!("{address}  ({label})"),
+                None => address.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+    fn json(&self) -> Value {
+        Value::Array(
+            self.0
+                .iter()
+                .map(|(address, label)| {
+                    serde_json::json!({
+                        "address": address.to_string(),
+                        "label": label,
+                    })
+                })
+                .collect(),
+        )
+    }
+    fn table(&self) -> String {
+        self.text()
+    }
+}
+
+/// A transaction of a wallet, with its label
+#[derive(Debug, Serialize)]
+pub struct LabeledTransaction {
+    #[serde(flatten)]
+    transaction: TransactionSummary,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+}
+impl crate::display::SerdeDisplay for LabeledTransaction {}
+
+impl LabeledTransaction {
+    pub fn new(transaction: TransactionSummary, labels: &WalletLabels) -> Self {
+        let label = labels.transactions.get(&transaction.txid).cloned();
+        Self { transaction, label }
+    }
+}
+
+/// An UTXO of a wallet, with its label
+#[derive(Debug, Serialize)]
+pub struct LabeledUtxo {
+    #[serde(flatten)]
+    utxo: HeritageUtxo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+}
+impl crate::display::SerdeDisplay for LabeledUtxo {}
+
+impl LabeledUtxo {
+    pub fn new(utxo: HeritageUtxo, labels: &WalletLabels) -> Self {
+        let label = labels.outputs.get(&utxo.outpoint).cloned();
+        Self { utxo, label }
+    }
+}
+
+/// What a label is attached to
+#[derive(Debug, Clone)]
+pub enum LabelRef {
+    Transaction(Txid),
+    Output(OutPoint),
+    Address(Address<NetworkUnchecked>),
+}
+
+impl FromStr for LabelRef {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.contains(':') {
+            Ok(Self::Output(OutPoint::from_str(s).map_err(Error::generic)?))
+        } else if let Ok(txid) = Txid::from_str(s) {
+            Ok(Self::Transaction(txid))
+        } else {
+            Ok(Self::Address(Address::from_str(s).map_err(Error::generic)?))
+        }
+    }
+}
+
+/// Wallet labels management subcommand.
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum WalletLabelSubcmd {
+    /// List the labels of the wallet, as BIP329 records
+    List,
+    /// Set the label of a transaction, an address or an UTXO, or remove it if no label is given
+    Set {
+        /// A transaction ID, an address or an UTXO (<TXID>:<VOUT>)
+        #[arg(value_name = "REF")]
+        reference: LabelRef,
+        /// The label
+        label: Option<String>,
+    },
+    /// Import labels from a BIP329 file (JSON Lines), e.g. exported by another wallet.
+    /// {n}Existing labels are overwritten, UTXOs marked as not spendable are frozen
+    Import {
+        /// The BIP329 file
+        #[arg(value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+        file: PathBuf,
+    },
+    /// Export the labels of the wallet in the BIP329 format (JSON Lines)
+    Export {
+        /// Write the labels to a file instead of displaying them
+        #[arg(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+        out: Option<PathBuf>,
+    },
+}
+
+impl super::CommandExecutor for WalletLabelSubcmd {
+    async fn execute(
+        self,
+        params: Box<dyn Any + Send>,
+    ) -> Result<Box<dyn crate::display::Displayable>> {
+        let (wallet, mut db): (Wallet, Database) = *params.downcast().unwrap();
+        let mut labels = WalletLabels::load_or_default(&db, wallet.name())?;
+
+        let res: Box<dyn crate::display::Displayable> = match self {
+            WalletLabelSubcmd::List => Box::new(labels.to_bip329()),
+            WalletLabelSubcmd::Set { reference, label } => {
+                match reference {
+                    LabelRef::Transaction(txid) => match label {
+                        Some(label) => labels.transactions.insert(txid, label),
+                        None => labels.transactions.remove(&txid),
+                    },
+                    LabelRef::Output(outpoint) => match label {
+                        Some(label) => labels.outputs.insert(outpoint, label),
+                        None => labels.outputs.remove(&outpoint),
+                    },
+                    LabelRef::Address(address) => {
+                        let address = address
+                            .require_network(bitcoin_network::get())
+                            .map_err(|e| Error::InvalidAddressNetwork(e.to_string()))?
+                            .to_string();
+                        match label {
+                            Some(label) => labels.addresses.insert(address, label),
+                            None => labels.addresses.remove(&address),
+                        }
+                    }
+                };
+                labels.store(&mut db)?;
+                Box::new("Label updated")
+            }
+            WalletLabelSubcmd::Import { file } => {
+                let content = std::fs::read_to_string(&file).map_err(|e| {
+                    log::error!("Could not read {}: {e}", file.display());
+                    Error::generic(e)
+                })?;
+                let (mut imported, mut skipped) = (0, 0);
+                for (i, line) in content.lines().enumerate() {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let record = serde_json::from_str::<Bip329Record>(line)
+                        .map_err(|e| Error::Generic(format!("Line {}: {e}", i + 1)))?;
+                    if labels
+                        .import_bip329(record)
+                        .map_err(|e| Error::Generic(format!("Line {}: {e}", i + 1)))?
+                    {
+                        imported += 1;
+                    } else {
+                        skipped += 1;
+                    }
+                }
+                labels.store(&mut db)?;
+                Box::new(format!(
+                    "{imported} label(s) imported, {skipped} skipped (not applicable to the wallet)"
+                ))
+            }
+            WalletLabelSubcmd::Export { out } => {
+                let content = labels
+                    .to_bip329()
+                    .iter()
+                    .map(serde_json::to_string)
+                    .collect::<core::result::Result<Vec<_>, _>>()?
+                    .join("\n");
+                if let Some(out) = out {
+                    std::fs::write(&out, content + "\n").map_err(|e| {
+                        log::error!("Could not write {}: {e}", out.display());
+                        Error::generic(e)
+                    })?;
+                    Box::new(format!("Labels exported to {}", out.display()))
+                } else {
+                    Box::new(content)
+                }
+            }
+        };
+        Ok(res)
+    }
+}
+
+/// The labels of a wallet, by address, UTXO (<TXID>:<VOUT>) or transaction ID
+pub(super) fn get_labels(db: &Database, wallet_name: &str) -> Result<HashMap<String, String>> {
+    let labels = WalletLabels::load_or_default(db, wallet_name)?;
+    let mut map = HashMap::new();
+    map.extend(
+        labels
+            .transactions
+            .into_iter()
+            .map(|(txid, label)| (txid.to_string(), label)),
+    );
+    map.extend(labels.addresses);
+    map.extend(
+        labels
+            .outputs
+            .into_iter()
+            .map(|(outpoint, label)| (outpoint.to_string(), label)),
+    );
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use btc_heritage_wallet::bitcoin::Network;
+
+    use super::*;
+
+    const TXID: &str = "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16";
+    const ADDRESS: &str = "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr";
+
+    fn outpoint(vout: u32) -> OutPoint {
+        OutPoint::from_str(&format!("{TXID}:{vout}")).unwrap()
+    }
+
+    #[test]
+    fn bip329_round_trip() {
+        bitcoin_network::set(Network::Bitcoin);
+        let labels = WalletLabels {
+            name: "wallet".to_owned(),
+            transactions: BTreeMap::from([(Txid::from_str(TXID).unwrap(), "Rent".to_owned())]),
+            addresses: BTreeMap::from([(ADDRESS.to_owned(), "Savings".to_owned())]),
+            outputs: BTreeMap::from([
+                (outpoint(0), "Change".to_owned()),
+                (outpoint(1), "Coinjoin".to_owned()),
+            ]),
+            frozen: BTreeSet::from([outpoint(1), outpoint(2)]),
+        };
+
+        let jsonl = labels
+            .to_bip329()
+            .iter()
+            .map(|record| serde_json::to_string(record).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(jsonl.len(), 5);
+        assert!(jsonl.contains(&format!(
+            r#"{{"type":"output","ref":"{TXID}:2","spendable":false}}"#
+        )));
+
+        let mut imported = WalletLabels::default();
+        for line in &jsonl {
+            let record = serde_json::from_str::<Bip329Record>(line).unwrap();
+            assert!(imported.import_bip329(record).unwrap());
+        }
+        assert_eq!(imported.transactions, labels.transactions);
+        assert_eq!(imported.addresses, labels.addresses);
+        assert_eq!(imported.outputs, labels.outputs);
+        assert_eq!(imported.frozen, labels.frozen);
+    }
+
+    #[test]
+    fn bip329_import_unfreezes_and_skips_other_types() {
+        let mut labels = WalletLabels {
+            frozen: BTreeSet::from([outpoint(0)]),
+            ..Default::default()
+        };
+        let record = |json: String| serde_json::from_str::<Bip329Record>(&json).unwrap();
+        assert!(labels
+            .import_bip329(record(format!(
+                r#"{{"type":"output","ref":"{TXID}:0","spendable":true}}"#
+            )))
+            .unwrap());
+        assert!(labels.frozen.is_empty());
+        assert!(labels.outputs.is_empty());
+        assert!(!labels
+            .import_bip329(record(format!(
+                r#"{{"type":"input","ref":"{TXID}:0","label":"Spent"}}"#
+            )))
+            .unwrap());
+        assert!(labels
+            .import_bip329(record(r#"{"type":"tx","ref":"invalid"}"#.to_owned()))
+            .is_err());
+    }
+}
@@ -1,12 +1,23 @@
 use core::any::Any;
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 
 use btc_heritage_wallet::{
-    errors::Result, ledger::WalletPolicy, Database, DatabaseItem, LedgerPolicy, OnlineWallet,
-    Wallet,
+    bitcoin::{
+        bip32::ChildNumber,
+        psbt::{Input, Psbt},
+        secp256k1::XOnlyPublicKey,
+    },
+    btc_heritage::HeritageWalletBackup,
+    errors::{Error, Result},
+    ledger::WalletPolicy,
+    AnyHeritageProvider, AnyKeyProvider, Database, DatabaseItem, HeirWallet, LedgerPolicy,
+    OnlineWallet, Wallet,
 };
 
-use crate::display::Displayable;
+use crate::{
+    display::{interactive_println, Displayable},
+    psbt_analysis::HeirLeaf,
+};
 
 /// Wallet Ledger Policy management subcommand.
 #[derive(Debug, Clone, clap::Subcommand)]
@@ -27,16 +38,68 @@ pub enum WalletLedgerPolicySubcmd {
     ClearRegistered,
 }
 
+/// The database items whose key-provider can hold registered Ledger policies
+enum PolicyOwner {
+    Wallet(Wallet),
+    HeirWallet(HeirWallet),
+}
+impl PolicyOwner {
+    fn key_provider(&self) -> &AnyKeyProvider {
+        match self {
+            PolicyOwner::Wallet(wallet) => wallet.key_provider(),
+            PolicyOwner::HeirWallet(heir_wallet) => heir_wallet.key_provider(),
+        }
+    }
+    fn key_provider_mut(&mut self) -> &mut AnyKeyProvider {
+        match self {
+            PolicyOwner::Wallet(wallet) => wallet.key_provider_mut(),
+            PolicyOwner::HeirWallet(heir_wallet) => heir_wallet.key_provider_mut(),
+        }
+    }
+    /// The descriptors backup from which the Ledger policies are extracted.
+    /// For an heir-wallet, only a local heritage-provider knows them: whatever the heritage-provider,
+    /// the policies needed to sign an inheritance are registered from its PSBT when signing it
+    /// (see [register_inheritance_policies])
+    async fn backup_descriptors(&self) -> Result<HeritageWalletBackup> {
+        match self {
+            PolicyOwner::Wallet(wallet) => wallet.online_wallet().backup_descriptors().await,
+            PolicyOwner::HeirWallet(heir_wallet) => match heir_wallet.heritage_provider() {
+                AnyHeritageProvider::LocalWallet(lw) => {
+                    lw.local_heritage_wallet().backup_descriptors().await
+                }
+                AnyHeritageProvider::None | AnyHeritageProvider::Service(_) => {
+                    Err(Error::IncorrectHeritageProvider("local"))
+                }
+            },
+        }
+    }
+    fn save(&self, db: &mut Database) -> Result<()> {
+        match self {
+            PolicyOwner::Wallet(wallet) => wallet.save(db),
+            PolicyOwner::HeirWallet(heir_wallet) => heir_wallet.save(db),
+        }
+    }
+}
+
 impl super::CommandExecutor for WalletLedgerPolicySubcmd {
     async fn execute(
         self,
         params: Box<dyn Any + Send>,
     ) -> Result<Box<dyn crate::display::Displayable>> {
-        let (mut wallet, mut db): (Wallet, Database) = *params.downcast().unwrap();
+        // Used by both the "wallet" and "heir-wallet" commands
+        let (mut wallet, mut db) = match params.downcast::<(Wallet, Database)>() {
+            Ok(params) => {
+                let (wallet, db) = *params;
+                (PolicyOwner::Wallet(wallet), db)
+            }
+            Err(params) => {
+                let (heir_wallet, db): (HeirWallet, Database) = *params.downcast().unwrap();
+                (PolicyOwner::HeirWallet(heir_wallet), db)
+            }
+        };
         let res: Box<dyn crate::display::Displayable> = match self {
             WalletLedgerPolicySubcmd::List => Box::new(
                 wallet
-                    .online_wallet()
                     .backup_descriptors()
                     .await?
                     .iter()
@@ -45,33 +108,25 @@ impl super::CommandExecutor for WalletLedgerPolicySubcmd {
                     .collect::<Vec<_>>(),
             ),
             WalletLedgerPolicySubcmd::ListRegistered => {
-                let btc_heritage_wallet::AnyKeyProvider::Ledger(ledger_wallet) =
-                    wallet.key_provider()
-                else {
-                    return Err(btc_heritage_wallet::errors::Error::IncorrectKeyProvider(
-                        "Ledger",
-                    ));
+                let AnyKeyProvider::Ledger(ledger_wallet) = wallet.key_provider() else {
+                    return Err(Error::IncorrectKeyProvider("Ledger"));
                 };
                 Box::new(ledger_wallet.list_registered_policies())
             }
             WalletLedgerPolicySubcmd::Register { policies } => {
-                let count = if let btc_heritage_wallet::AnyKeyProvider::Ledger(ledger_wallet) =
-                    wallet.key_provider_mut()
+                let count = if let AnyKeyProvider::Ledger(ledger_wallet) = wallet.key_provider_mut()
                 {
                     ledger_wallet
                         .register_policies(&policies, display_wallet_policy)
                         .await?
                 } else {
-                    return Err(btc_heritage_wallet::errors::Error::IncorrectKeyProvider(
-                        "Ledger",
-                    ));
+                    return Err(Error::IncorrectKeyProvider("Ledger"));
                 };
                 wallet.save(&mut db)?;
                 Box::new(format!("{count} policies registered"))
             }
             WalletLedgerPolicySubcmd::AutoRegister => {
-                let policies = if let btc_heritage_wallet::AnyKeyProvider::Ledger(ledger_wallet) =
-                    wallet.key_provider()
+                let policies = if let AnyKeyProvider::Ledger(ledger_wallet) = wallet.key_provider()
                 {
                     let registered_policy_ids = ledger_wallet
                         .list_registered_policies()
@@ -79,7 +134,6 @@ impl super::CommandExecutor for WalletLedgerPolicySubcmd {
                         .map(|(id, ..)| id)
                         .collect::<HashSet<_>>();
                     wallet
-                        .online_wallet()
                         .backup_descriptors()
                         .await?
                         .iter()
@@ -97,13 +151,10 @@ impl super::CommandExecutor for WalletLedgerPolicySubcmd {
                         .filter(|p| !registered_policy_ids.contains(&p.get_account_id()))
                         .collect::<Vec<_>>()
                 } else {
-                    return Err(btc_heritage_wallet::errors::Error::IncorrectKeyProvider(
-                        "Ledger",
-                    ));
+                    return Err(Error::IncorrectKeyProvider("Ledger"));
                 };
                 log::info!("{} new policies to register", policies.len());
-                let count = if let btc_heritage_wallet::AnyKeyProvider::Ledger(ledger_wallet) =
-                    wallet.key_provider_mut()
+                let count = if let AnyKeyProvider::Ledger(ledger_wallet) = wallet.key_provider_mut()
                 {
                     ledger_wallet
                         .register_policies(&policies, display_wallet_policy)
@@ -115,14 +166,11 @@ impl super::CommandExecutor for WalletLedgerPolicySubcmd {
                 Box::new(format!("{count} new policies registered"))
             }
             WalletLedgerPolicySubcmd::ClearRegistered => {
-                let count = if let btc_heritage_wallet::AnyKeyProvider::Ledger(ledger_wallet) =
-                    wallet.key_provider_mut()
+                let count = if let AnyKeyProvider::Ledger(ledger_wallet) = wallet.key_provider_mut()
                 {
                     ledger_wallet.clear_registered_policies()
                 } else {
-                    return Err(btc_heritage_wallet::errors::Error::IncorrectKeyProvider(
-                        "Ledger",
-                    ));
+                    return Err(Error::IncorrectKeyProvider("Ledger"));
                 };
                 wallet.save(&mut db)?;
                 Box::new(format!("{count} policies cleared"))
@@ -133,11 +181,169 @@ impl super::CommandExecutor for WalletLedgerPolicySubcmd {
 }
 
 fn display_wallet_policy(wallet_policy: &WalletPolicy) {
-    println!("\x1b[4mRegister account\x1b[0m");
-    wallet_policy.display();
-    println!();
+    interactive_println("\x1b[4mRegister account\x1b[0m");
+    interactive_println(&wallet_policy.text());
+    interactive_println("");
 }
 
 fn parse_ledger_policies(val: &str) -> Result<LedgerPolicy> {
     Ok(val.try_into()?)
 }
+
+/// Register on the Ledger of an heir-wallet the policies needed to sign an inheritance PSBT,
+/// if they are not registered yet. Does nothing if the key-provider is not a Ledger.
+///
+/// The policies are rebuilt from the Taproot scripts and the global extended public keys
+/// of the PSBT, which both the local and the service heritage-providers produce.
+pub(super) async fn register_inheritance_policies(
+    heir_wallet: &mut HeirWallet,
+    db: &mut Database,
+    psbt: &Psbt,
+) -> Result<()> {
+    let AnyKeyProvider::Ledger(ledger_wallet) = heir_wallet.key_provider_mut() else {
+        return Ok(());
+    };
+    let registered_policy_ids = ledger_wallet
+        .list_registered_policies()
+        .into_iter()
+        .map(|(id, ..)| id)
+        .collect::<HashSet<_>>();
+    let policies = inheritance_policies(psbt)?
+        .into_iter()
+        .filter(|p| !registered_policy_ids.contains(&p.get_account_id()))
+        .collect::<Vec<_>>();
+    if policies.is_empty() {
+        return Ok(());
+    }
+    log::info!("{} new policies to register", policies.len());
+    let count = ledger_wallet
+        .register_policies(&policies, display_wallet_policy)
+        .await?;
+    log::info!("{count} new policies registered");
+    heir_wallet.save(db)
+}
+
+/// The Ledger policies of the Taproot descriptors spent by the inputs of a PSBT
+fn inheritance_policies(psbt: &Psbt) -> Result<Vec<LedgerPolicy>> {
+    psbt.inputs
+        .iter()
+        .enumerate()
+        .map(|(i, input)| {
+            input_descriptor(psbt, input).ok_or_else(|| {
+                Error::Generic(format!(
+                    "Cannot rebuild the descriptor of input #{i} from the PSBT, \
+                    its Taproot scripts or extended public keys are missing"
+                ))
+            })
+        })
+        .collect::<Result<BTreeSet<_>>>()?
+        .iter()
+        .map(|descriptor| Ok(LedgerPolicy::try_from(descriptor.as_str())?))
+        .collect()
+}
+
+/// Rebuild the descriptor spent by an input, in the Ledger policy format
+/// (keys with their origin and a `/**` suffix)
+fn input_descriptor(psbt: &Psbt, input: &Input) -> Option<String> {
+    let key_expression = |key: &XOnlyPublicKey| {
+        let (_, (fingerprint, path)) = input.tap_key_origins.get(key)?;
+        // The key is derived from an account extended public key with <change>/<index>
+        let path: &[ChildNumber] = path.as_ref();
+        let (xpub, xpub_path) = psbt.xpub.iter().find_map(|(xpub, (xfg, xpath))| {
+            let xpath: &[ChildNumber] = xpath.as_ref();
+            (xfg == fingerprint && path.len() == xpath.len() + 2 && path.starts_with(xpath))
+                .then_some((xpub, xpath))
+        })?;
+        let origin = core::iter::once(fingerprint.to_string())
+            .chain(xpub_path.iter().map(ToString::to_string))
+            .collect::<Vec<_>>()
+            .join("/");
+        Some(format!("[{origin}]{xpub}/**"))
+    };
+    let leaves = input
+        .tap_scripts
+        .iter()
+        .map(|(control_block, (script, _))| {
+            let leaf = HeirLeaf::parse(script);
+            let key = key_expression(&leaf.key?)?;
+            let expression = match (leaf.relative_lock, leaf.absolute_lock) {
+                (Some(relative), Some(absolute)) => {
+                    format!("and_v(v:pk({key}),and_v(v:older({relative}),after({absolute})))")
+                }
+                (None, Some(absolute)) => format!("and_v(v:pk({key}),after({absolute}))"),
+                _ => return None,
+            };
+            Some((expression, control_block.merkle_branch.as_inner().to_vec()))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let internal_key = key_expression(&input.tap_internal_key?)?;
+    Some(match taptree_expression(leaves) {
+        Some(tree) => format!("tr({internal_key},{tree})"),
+        None => format!("tr({internal_key})"),
+    })
+}
+
+/// Rebuild the Taproot tree expression of leaves given with their merkle branch,
+/// ordered from the leaf to the root. [None] if there is no leaf or the branches are inconsistent.
+fn taptree_expression<H: PartialEq>(leaves: Vec<(String, Vec<H>)>) -> Option<String> {
+    if leaves.len() == 1 && leaves[0].1.is_empty() {
+        return leaves.into_iter().next().map(|(expression, _)| expression);
+    }
+    // The last node of the branch of a leaf is the other child of the root:
+    // it splits the leaves between the two subtrees
+    let mut subtrees: Vec<(H, Vec<(String, Vec<H>)>)> = vec![];
+    for (expression, mut branch) in leaves {
+        let sibling = branch.pop()?;
+        match subtrees.iter_mut().find(|(s, _)| *s == sibling) {
+            Some((_, subtree)) => subtree.push((expression, branch)),
+            None => subtrees.push((sibling, vec![(expression, branch)])),
+        }
+    }
+    let [(_, left), (_, right)] = <[_; 2]>::try_from(subtrees).ok()?;
+    Some(format!(
+        "{{{},{}}}",
+        taptree_expression(left)?,
+        taptree_expression(right)?
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(name: &str, branch: &[u8]) -> (String, Vec<u8>) {
+        (name.to_owned(), branch.to_vec())
+    }
+
+    #[test]
+    fn taptree_single_leaf() {
+        assert_eq!(
+            taptree_expression(vec![leaf("a", &[])]),
+            Some("a".to_owned())
+        );
+        assert_eq!(taptree_expression::<u8>(vec![]), None);
+    }
+
+    #[test]
+    fn taptree_nested_leaves() {
+        // {a,{b,c}}: 0 is the hash of a, 1 of {b,c}, 2 of b and 3 of c
+        let tree = taptree_expression(vec![
+            leaf("a", &[1]),
+            leaf("b", &[3, 0]),
+            leaf("c", &[2, 0]),
+        ]);
+        assert_eq!(tree, Some("{a,{b,c}}".to_owned()));
+    }
+
+    #[test]
+    fn taptree_inconsistent_branches() {
+        assert_eq!(
+            taptree_expression(vec![leaf("a", &[]), leaf("b", &[0])]),
+            None
+        );
+        assert_eq!(
+            taptree_expression(vec![leaf("a", &[1]), leaf("b", &[2]), leaf("c", &[3])]),
+            None
+        );
+    }
+}
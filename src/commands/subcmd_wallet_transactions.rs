@@ -0,0 +1,443 @@
+use core::any::Any;
+use std::path::PathBuf;
+
+use btc_heritage_wallet::{
+    bitcoin::{psbt::Psbt, Txid},
+    errors::{Error, Result},
+    heritage_service_api_client::{NewTxSpendingConfig, TransactionSummary},
+    Database, DatabaseItem, OnlineWallet, Wallet,
+};
+use chrono::{DateTime, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use super::subcmd_wallet_labels::WalletLabels;
+use crate::{display::Displayable, exit_status::ExitStatus};
+
+/// A transaction signed by a wallet, kept to be able to replace or accelerate it later because
+/// the wallet history does not retain the outputs that are not owned by the wallet.
+/// It is named after its wallet and the ID of the transaction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionRecord {
+    name: String,
+    /// The PSBT of the transaction, as created
+    pub psbt: Psbt,
+    /// The recipients of the transaction, or the address receiving all its inputs
+    pub spending_config: NewTxSpendingConfig,
+}
+
+impl DatabaseItem for TransactionRecord {
+    fn item_key_prefix() -> &'static str {
+        "transaction_record#"
+    }
+    fn item_default_name_key() -> &'static str {
+        "default_transaction_record_name"
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn rename(&mut self, new_name: String) {
+        self.name = new_name;
+    }
+}
+
+impl TransactionRecord {
+    fn record_name(wallet_name: &str, txid: &str) -> String {
+        format!("{wallet_name}#{txid}")
+    }
+    /// The record of a transaction created by the wallet, to store once it is signed
+    pub fn new(wallet_name: &str, psbt: &Psbt, spending_config: NewTxSpendingConfig) -> Self {
+        Self {
+            name: Self::record_name(wallet_name, &psbt.unsigned_tx.txid().to_string()),
+            psbt: psbt.clone(),
+            spending_config,
+        }
+    }
+    /// Store the record, replacing any previous one, if the transaction was signed.
+    /// An unsigned PSBT, or one whose signing was refused or failed, is never broadcasted
+    /// by the wallet so it will not have to be replaced.
+    pub fn store_if_signed(
+        &self,
+        db: &mut Database,
+        sign: bool,
+        result: &dyn Displayable,
+    ) -> Result<()> {
+        if !sign
+            || !matches!(
+                result.exit_status(),
+                ExitStatus::Success | ExitStatus::BroadcastFailed
+            )
+        {
+            return Ok(());
+        }
+        if Self::list_names(db)?.contains(&self.name) {
+            self.save(db)
+        } else {
+            self.create(db)
+        }
+    }
+    /// The record of a transaction, None if it was not signed by the wallet
+    pub fn find(db: &Database, wallet_name: &str, txid: Txid) -> Result<Option<Self>> {
+        let name = Self::record_name(wallet_name, &txid.to_string());
+        if Self::list_names(db)?.contains(&name) {
+            Ok(Some(Self::load(db, &name)?))
+        } else {
+            Ok(None)
+        }
+    }
+    /// The names of the records of a wallet, with the ID of their transaction
+    fn wallet_record_names(db: &Database, wallet_name: &str) -> Result<Vec<(String, String)>> {
+        Ok(Self::list_names(db)?
+            .into_iter()
+            .filter_map(|name| {
+                // Transaction IDs do not contain '#', wallet names may
+                let (wallet, txid) = name.rsplit_once('#')?;
+                (wallet == wallet_name).then(|| (name.clone(), txid.to_owned()))
+            })
+            .collect())
+    }
+    /// Delete the records of a wallet
+    pub fn delete_all(db: &mut Database, wallet_name: &str) -> Result<()> {
+        for (name, _) in Self::wallet_record_names(db, wallet_name)? {
+            Self::load(db, &name)?.delete(db)?;
+        }
+        Ok(())
+    }
+    /// Move the records of a wallet to its new name
+    pub fn rename_all(db: &mut Database, wallet_name: &str, new_wallet_name: &str) -> Result<()> {
+        for (name, txid) in Self::wallet_record_names(db, wallet_name)? {
+            Self::load(db, &name)?.db_rename(db, Self::record_name(new_wallet_name, &txid))?;
+        }
+        Ok(())
+    }
+}
+
+/// Wallet transactions subcommand.
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum WalletTransactionSubcmd {
+    /// Export the transaction history of the wallet for accounting purposes, oldest first,
+    /// with the net amount, the fee and the running balance of each transaction
+    Export {
+        /// The format of the export
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        /// Only export the transactions confirmed on or after this date (YYYY-MM-DD)
+        #[arg(long, value_name = "DATE")]
+        from: Option<NaiveDate>,
+        /// Only export the transactions confirmed on or before this date (YYYY-MM-DD)
+        #[arg(long, value_name = "DATE")]
+        to: Option<NaiveDate>,
+        /// Write the export to a file instead of displaying it
+        #[arg(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+        out: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// Comma-separated values, with a header line
+    Csv,
+    /// A JSON array
+    Json,
+    /// A ledger-cli journal, unconfirmed transactions are omitted
+    LedgerCli,
+}
+
+/// A transaction of the wallet, as seen by an accountant
+#[derive(Debug, Serialize)]
+struct ExportedTransaction {
+    /// The confirmation date (UTC), None if unconfirmed
+    #[serde(serialize_with = "serialize_date")]
+    date: Option<NaiveDate>,
+    txid: String,
+    /// The amount received (positive) or spent, fee included (negative), in sat
+    net_amount: i64,
+    /// The fee paid by the wallet, in sat
+    fee: u64,
+    /// The balance of the wallet after this transaction, in sat
+    balance: i64,
+    confirmation_height: Option<u32>,
+    labels: Vec<String>,
+}
+impl crate::display::SerdeDisplay for ExportedTransaction {}
+
+fn serialize_date<S: serde::Serializer>(
+    date: &Option<NaiveDate>,
+    serializer: S,
+) -> core::result::Result<S::Ok, S::Error> {
+    date.map(|date| date.to_string()).serialize(serializer)
+}
+
+impl super::CommandExecutor for WalletTransactionSubcmd {
+    async fn execute(
+        self,
+        params: Box<dyn Any + Send>,
+    ) -> Result<Box<dyn crate::display::Displayable>> {
+        let (wallet, db): (Wallet, Database) = *params.downcast().unwrap();
+
+        let res: Box<dyn crate::display::Displayable> = match self {
+            WalletTransactionSubcmd::Export {
+                format,
+                from,
+                to,
+                out,
+            } => {
+                let transactions = wallet.online_wallet().list_transactions().await?;
+                let exported = export_transactions(transactions, &wallet, &db)?
+                    .into_iter()
+                    .filter(|tx| match (tx.date, from, to) {
+                        (_, None, None) => true,
+                        // Unconfirmed transactions have no date to compare
+                        (None, _, _) => false,
+                        (Some(date), from, to) => {
+                            from.map_or(true, |from| date >= from)
+                                && to.map_or(true, |to| date <= to)
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let count = exported.len();
+                let content = match format {
+                    ExportFormat::Csv => to_csv(&exported),
+                    ExportFormat::Json => {
+                        if out.is_none() {
+                            return Ok(Box::new(exported));
+                        }
+                        serde_json::to_string_pretty(&exported)?
+                    }
+                    ExportFormat::LedgerCli => to_ledger_cli(&exported, wallet.name()),
+                };
+                if let Some(out) = out {
+                    std::fs::write(&out, content).map_err(|e| {
+                        log::error!("Could not write {}: {e}", out.display());
+                        Error::generic(e)
+                    })?;
+                    Box::new(format!(
+                        "{count} transaction(s) exported to {}",
+                        out.display()
+                    ))
+                } else {
+                    Box::new(content)
+                }
+            }
+        };
+        Ok(res)
+    }
+}
+
+/// Compute the accounting view of every transaction, in chronological order
+fn export_transactions(
+    mut transactions: Vec<TransactionSummary>,
+    wallet: &Wallet,
+    db: &Database,
+) -> Result<Vec<ExportedTransaction>> {
+    let wallet_labels = WalletLabels::load_or_default(db, wallet.name())?;
+    // Unconfirmed transactions last
+    transactions.sort_by_key(|tx| {
+        tx.confirmation_time
+            .as_ref()
+            .map_or((u32::MAX, u64::MAX), |bt| (bt.height, bt.timestamp))
+    });
+    let mut balance = 0i64;
+    Ok(transactions
+        .into_iter()
+        .map(|tx| {
+            let received = tx
+                .owned_outputs
+                .iter()
+                .map(|io| io.amount.to_sat())
+                .sum::<u64>();
+            let spent = tx
+                .owned_inputs
+                .iter()
+                .map(|io| io.amount.to_sat())
+                .sum::<u64>();
+            let net_amount = received as i64 - spent as i64;
+            balance += net_amount;
+            let labels = wallet_labels
+                .transactions
+                .get(&tx.txid)
+                .into_iter()
+                .chain(
+                    tx.owned_inputs
+                        .iter()
+                        .chain(tx.owned_outputs.iter())
+                        .filter_map(|io| wallet_labels.outputs.get(&io.outpoint)),
+                )
+                .cloned()
+                .collect();
+            ExportedTransaction {
+                date: tx.confirmation_time.as_ref().map(|bt| {
+                    DateTime::from_timestamp(bt.timestamp as i64, 0)
+                        .expect("timestamp is in range")
+                        .date_naive()
+                }),
+                txid: tx.txid.to_string(),
+                net_amount,
+                // Only the spender pays the fee
+                fee: if tx.owned_inputs.is_empty() {
+                    0
+                } else {
+                    tx.fee.to_sat()
+                },
+                balance,
+                confirmation_height: tx.confirmation_time.as_ref().map(|bt| bt.height),
+                labels,
+            }
+        })
+        .collect())
+}
+
+fn to_csv(transactions: &[ExportedTransaction]) -> String {
+    let mut lines =
+        vec!["date,txid,net_amount_sat,fee_sat,balance_sat,confirmation_height,labels".to_owned()];
+    for tx in transactions {
+        lines.push(format!(
+            "{},{},{},{},{},{},{}",
+            tx.date.map(|d| d.to_string()).unwrap_or_default(),
+            tx.txid,
+            tx.net_amount,
+            tx.fee,
+            tx.balance,
+            tx.confirmation_height
+                .map(|h| h.to_string())
+                .unwrap_or_default(),
+            csv_escape(&tx.labels.join("; "))
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Quote a CSV field if needed (RFC 4180)
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn to_ledger_cli(transactions: &[ExportedTransaction], wallet_name: &str) -> String {
+    let mut entries = vec![];
+    for tx in transactions {
+        let Some(date) = tx.date else {
+            continue;
+        };
+        let mut entry = vec![format!("{} * {}", date.format("%Y/%m/%d"), tx.txid)];
+        if !tx.labels.is_empty() {
+            entry.push(format!("    ; {}", tx.labels.join("; ")));
+        }
+        entry.push(format!(
+            "    Assets:Bitcoin:{wallet_name}  {} BTC",
+            format_btc(tx.net_amount)
+        ));
+        if tx.net_amount >= 0 {
+            entry.push("    Income:Bitcoin".to_owned());
+        } else {
+            if tx.fee > 0 {
+                entry.push(format!(
+                    "    Expenses:Bitcoin:Fees  {} BTC",
+                    format_btc(tx.fee as i64)
+                ));
+            }
+            entry.push("    Expenses:Bitcoin".to_owned());
+        }
+        entries.push(entry.join("\n"));
+    }
+    entries.join("\n\n")
+}
+
+/// A signed amount of sat as BTC, with all 8 decimals
+fn format_btc(sat: i64) -> String {
+    let sign = if sat < 0 { "-" } else { "" };
+    let sat = sat.unsigned_abs();
+    format!("{sign}{}.{:08}", sat / 100_000_000, sat % 100_000_000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(
+        date: Option<(i32, u32, u32)>,
+        net_amount: i64,
+        fee: u64,
+        labels: &[&str],
+    ) -> ExportedTransaction {
+        ExportedTransaction {
+            date: date.map(|(y, m, d)| NaiveDate::from_ymd_opt(y, m, d).unwrap()),
+            txid: "txid".to_owned(),
+            net_amount,
+            fee,
+            balance: 0,
+            confirmation_height: date.map(|_| 800_000),
+            labels: labels.iter().map(|l| (*l).to_owned()).collect(),
+        }
+    }
+
+    #[test]
+    fn btc_amounts() {
+        assert_eq!(format_btc(0), "0.00000000");
+        assert_eq!(format_btc(1), "0.00000001");
+        assert_eq!(format_btc(123_456_789), "1.23456789");
+        assert_eq!(format_btc(-100_000_000), "-1.00000000");
+        assert_eq!(format_btc(-1_500), "-0.00001500");
+        assert_eq!(format_btc(i64::MIN), "-92233720368.54775808");
+    }
+
+    #[test]
+    fn csv_labels_are_escaped() {
+        assert_eq!(csv_escape("rent"), "rent");
+        assert_eq!(csv_escape("rent, june"), "\"rent, june\"");
+        assert_eq!(csv_escape("the \"big\" one"), "\"the \"\"big\"\" one\"");
+        assert_eq!(csv_escape("two\nlines"), "\"two\nlines\"");
+
+        let csv = to_csv(&[
+            transaction(Some((2024, 6, 1)), 10_000, 0, &["salary, june", "bonus"]),
+            transaction(None, -2_500, 500, &[]),
+        ]);
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "date,txid,net_amount_sat,fee_sat,balance_sat,confirmation_height,labels",
+                "2024-06-01,txid,10000,0,0,800000,\"salary, june; bonus\"",
+                ",txid,-2500,500,0,,",
+            ]
+        );
+    }
+
+    #[test]
+    fn ledger_postings_balance() {
+        let journal = to_ledger_cli(
+            &[
+                transaction(Some((2024, 6, 1)), 10_000, 0, &["salary"]),
+                transaction(None, -1_000, 100, &[]),
+                transaction(Some((2024, 6, 2)), -2_500, 500, &[]),
+                transaction(Some((2024, 6, 3)), -300, 0, &[]),
+            ],
+            "main",
+        );
+        let entries = journal.split("\n\n").collect::<Vec<_>>();
+        // The unconfirmed transaction is omitted
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[0],
+            "2024/06/01 * txid\n    ; salary\n    Assets:Bitcoin:main  0.00010000 BTC\n    Income:Bitcoin"
+        );
+        assert_eq!(
+            entries[1],
+            "2024/06/02 * txid\n    Assets:Bitcoin:main  -0.00002500 BTC\n    Expenses:Bitcoin:Fees  0.00000500 BTC\n    Expenses:Bitcoin"
+        );
+        assert_eq!(
+            entries[2],
+            "2024/06/03 * txid\n    Assets:Bitcoin:main  -0.00000300 BTC\n    Expenses:Bitcoin"
+        );
+        // Each entry leaves exactly one posting without amount, for ledger to balance it
+        for entry in entries {
+            let postings = entry
+                .lines()
+                .skip(1)
+                .filter(|l| !l.trim_start().starts_with(';'));
+            assert_eq!(postings.filter(|l| !l.ends_with(" BTC")).count(), 1);
+        }
+    }
+}
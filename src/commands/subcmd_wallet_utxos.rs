@@ -0,0 +1,205 @@
+use btc_heritage_wallet::{
+    bitcoin::{Amount, OutPoint},
+    btc_heritage::utils::timestamp_now,
+    errors::{Error, Result},
+    heritage_service_api_client::HeritageUtxo,
+    Database, OnlineWallet, Wallet,
+};
+use chrono::DateTime;
+use core::any::Any;
+use serde::Serialize;
+
+use super::subcmd_wallet_labels::WalletLabels;
+
+/// Wallet UTXOs coin control subcommand.
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum WalletUtxoSubcmd {
+    /// List the UTXOs of the wallet with their label and frozen state
+    List {
+        /// Only list the UTXOs locked by this Heritage Configuration,
+        /// 0 being the current one (see the "heritage-configs list" command)
+        #[arg(long, value_name = "INDEX")]
+        heritage_config: Option<usize>,
+        /// Only list the UTXOs of at least this amount e.g. 0.1btc, 100000sat
+        #[arg(long, value_name = "AMOUNT")]
+        min_amount: Option<Amount>,
+        /// Only list the UTXOs of at most this amount e.g. 0.1btc, 100000sat
+        #[arg(long, value_name = "AMOUNT")]
+        max_amount: Option<Amount>,
+        /// Only list the UTXOs confirmed more than this number of days ago
+        #[arg(long, value_name = "DAYS")]
+        older_than: Option<u64>,
+        /// Only list the frozen UTXOs
+        #[arg(long, default_value_t = false, conflicts_with = "unfrozen")]
+        frozen: bool,
+        /// Only list the UTXOs that are not frozen
+        #[arg(long, default_value_t = false)]
+        unfrozen: bool,
+    },
+    /// Set the label of an UTXO, or remove it if no label is given
+    Label {
+        /// The UTXO to label
+        outpoint: OutPoint,
+        /// The label
+        label: Option<String>,
+    },
+    /// Freeze UTXOs, they will not be spent by the wallet unless explicitly included with "send-bitcoins --include".
+    /// {n}They are neither added to "bump-fee" replacements, nor spent by "cpfp" or "heritage-configs renew --consolidate"
+    Freeze {
+        /// The UTXO(s) to freeze
+        #[arg(value_name = "OUTPOINT", num_args = 1.., required = true)]
+        outpoints: Vec<OutPoint>,
+    },
+    /// Unfreeze UTXOs, they can be spent again by the wallet
+    Unfreeze {
+        /// The UTXO(s) to unfreeze
+        #[arg(value_name = "OUTPOINT", num_args = 1.., required = true)]
+        outpoints: Vec<OutPoint>,
+    },
+}
+
+/// An UTXO of the wallet with its coin control settings
+#[derive(Debug, Serialize)]
+struct ControlledUtxo {
+    outpoint: OutPoint,
+    amount: u64,
+    confirmation_height: Option<u32>,
+    confirmation_date: Option<String>,
+    /// The index of the Heritage Configuration locking the UTXO, 0 being the current one
+    heritage_config: Option<usize>,
+    label: Option<String>,
+    frozen: bool,
+}
+impl crate::display::SerdeDisplay for ControlledUtxo {}
+
+impl super::CommandExecutor for WalletUtxoSubcmd {
+    async fn execute(
+        self,
+        params: Box<dyn Any + Send>,
+    ) -> Result<Box<dyn crate::display::Displayable>> {
+        let (wallet, mut db): (Wallet, Database) = *params.downcast().unwrap();
+        let mut labels = WalletLabels::load_or_default(&db, wallet.name())?;
+        let utxos = wallet.online_wallet().list_heritage_utxos().await?;
+
+        let res: Box<dyn crate::display::Displayable> = match self {
+            WalletUtxoSubcmd::List {
+                heritage_config,
+                min_amount,
+                max_amount,
+                older_than,
+                frozen,
+                unfrozen,
+            } => {
+                let heritage_configs = wallet
+                    .list_subwallet_configs()
+                    .await?
+                    .into_iter()
+                    .map(|meta| meta.heritage_config)
+                    .collect::<Vec<_>>();
+                let now = timestamp_now();
+                let min_age = older_than.map(days_to_seconds).transpose()?;
+                let res = utxos
+                    .into_iter()
+                    .filter(|utxo| {
+                        min_amount.map_or(true, |min| utxo.amount >= min)
+                            && max_amount.map_or(true, |max| utxo.amount <= max)
+                            && min_age.map_or(true, |age| {
+                                utxo.confirmation_time
+                                    .as_ref()
+                                    .is_some_and(|bt| bt.timestamp.saturating_add(age) <= now)
+                            })
+                    })
+                    .map(|utxo| {
+                        let HeritageUtxo {
+                            outpoint,
+                            amount,
+                            confirmation_time,
+                            heritage_config,
+                            ..
+                        } = utxo;
+                        ControlledUtxo {
+                            outpoint,
+                            amount: amount.to_sat(),
+                            confirmation_height: confirmation_time.as_ref().map(|bt| bt.height),
+                            confirmation_date: confirmation_time.as_ref().map(|bt| {
+                                DateTime::from_timestamp(bt.timestamp as i64, 0)
+                                    .expect("timestamp is in range")
+                                    .to_string()
+                            }),
+                            heritage_config: heritage_configs
+                                .iter()
+                                .position(|hc| *hc == heritage_config),
+                            label: labels.outputs.get(&outpoint).cloned(),
+                            frozen: labels.frozen.contains(&outpoint),
+                        }
+                    })
+                    .filter(|utxo| {
+                        heritage_config.map_or(true, |index| utxo.heritage_config == Some(index))
+                            && (!frozen || utxo.frozen)
+                            && (!unfrozen || !utxo.frozen)
+                    })
+                    .collect::<Vec<_>>();
+                Box::new(res)
+            }
+            WalletUtxoSubcmd::Label { outpoint, label } => {
+                if let Some(label) = label {
+                    verify_wallet_utxos(&utxos, core::slice::from_ref(&outpoint))?;
+                    labels.outputs.insert(outpoint, label);
+                } else {
+                    labels.outputs.remove(&outpoint);
+                }
+                labels.store(&mut db)?;
+                Box::new("UTXO label updated")
+            }
+            WalletUtxoSubcmd::Freeze { outpoints } => {
+                verify_wallet_utxos(&utxos, &outpoints)?;
+                labels.frozen.extend(outpoints);
+                labels.store(&mut db)?;
+                Box::new("UTXO(s) frozen")
+            }
+            WalletUtxoSubcmd::Unfreeze { outpoints } => {
+                // Do not verify, the UTXOs may have been spent in the meantime
+                for outpoint in outpoints {
+                    labels.frozen.remove(&outpoint);
+                }
+                labels.store(&mut db)?;
+                Box::new("UTXO(s) unfrozen")
+            }
+        };
+        Ok(res)
+    }
+}
+
+/// Convert a number of days to seconds, failing if it overflows
+fn days_to_seconds(days: u64) -> Result<u64> {
+    days.checked_mul(86400)
+        .ok_or_else(|| Error::Generic(format!("{days} days is too long")))
+}
+
+fn verify_wallet_utxos(utxos: &[HeritageUtxo], outpoints: &[OutPoint]) -> Result<()> {
+    if let Some(unknown) = outpoints
+        .iter()
+        .find(|outpoint| !utxos.iter().any(|utxo| utxo.outpoint == **outpoint))
+    {
+        return Err(Error::Generic(format!(
+            "{unknown} is not an UTXO of the wallet"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn days_to_seconds_rejects_overflows() {
+        assert_eq!(days_to_seconds(0).unwrap(), 0);
+        assert_eq!(days_to_seconds(30).unwrap(), 30 * 86400);
+        assert_eq!(
+            days_to_seconds(u64::MAX / 86400).unwrap(),
+            u64::MAX / 86400 * 86400
+        );
+        assert!(days_to_seconds(u64::MAX / 86400 + 1).is_err());
+    }
+}
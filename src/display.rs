@@ -1,8 +1,14 @@
+use std::{
+    io::{stderr, stdout, IsTerminal},
+    sync::OnceLock,
+};
+
 use btc_heritage_wallet::{
     btc_heritage::{
         heritage_wallet::WalletAddress, AccountXPub, BlockInclusionObjective, HeirConfig,
         HeritageConfig, HeritageWalletBackup,
     },
+    errors::Error,
     heritage_service_api_client::{
         AccountXPubWithStatus, Fingerprint, Heir, Heritage, HeritageUtxo, HeritageWalletMeta,
         SubwalletConfigMeta, TransactionSummary,
@@ -12,29 +18,313 @@ use btc_heritage_wallet::{
     online_wallet::WalletStatus,
     LedgerPolicy, PsbtSummary,
 };
+use serde_json::{json, Value};
+
+use crate::exit_status::ExitStatus;
+
+/// The format used to output the result of a command
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable output
+    #[default]
+    Text,
+    /// A stable JSON envelope: {"ok":true,"result":...} or {"ok":false,"error":...}
+    Json,
+    /// Human-readable tables, when the result is tabular
+    Table,
+}
+
+static OUTPUT_FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Set the output format for the whole process. Only the first call has an effect.
+pub fn set_output_format(output_format: OutputFormat) {
+    if OUTPUT_FORMAT.set(output_format).is_err() {
+        log::warn!("Output format already set");
+    }
+}
+
+/// Get the output format of the process, [OutputFormat::Text] if never set
+pub fn output_format() -> OutputFormat {
+    OUTPUT_FORMAT.get().copied().unwrap_or_default()
+}
 
 pub trait Displayable {
-    fn display(&self);
+    /// The human-readable representation, used by [OutputFormat::Text]
+    fn text(&self) -> String;
+    /// The machine-readable representation, used as the `result` of the JSON envelope
+    fn json(&self) -> Value;
+    /// The tabular representation, used by [OutputFormat::Table]
+    fn table(&self) -> String {
+        json_to_table(&self.json()).unwrap_or_else(|| self.text())
+    }
+    /// The exit status of the process when this is the result of the command
+    fn exit_status(&self) -> ExitStatus {
+        ExitStatus::Success
+    }
+    /// Print the result on stdout using the process [OutputFormat]
+    fn display(&self) {
+        let output = match output_format() {
+            OutputFormat::Text => self.text(),
+            OutputFormat::Table => self.table(),
+            OutputFormat::Json => {
+                println!("{}", json!({"ok": true, "result": self.json()}));
+                return;
+            }
+        };
+        // Empty results, such as (), do not even output an empty line
+        if !output.is_empty() {
+            print_stdout(&output)
+        }
+    }
+}
+
+/// Display the result of a command, or its error, using the process [OutputFormat]
+/// and return the corresponding exit status
+pub fn render(result: Result<Box<dyn Displayable>, Error>) -> ExitStatus {
+    match result {
+        Ok(displayable) => {
+            displayable.display();
+            displayable.exit_status()
+        }
+        Err(e) => {
+            let exit_status = ExitStatus::from(&e);
+            match output_format() {
+                OutputFormat::Text | OutputFormat::Table => log::error!("{e}"),
+                OutputFormat::Json => println!(
+                    "{}",
+                    json!({"ok": false, "exit_code": exit_status.code(), "error": e.to_string()})
+                ),
+            }
+            exit_status
+        }
+    }
+}
+
+/// A result that is displayed as usual but signals the command did not fully succeed,
+/// for example a PSBT returned because it could not be signed or broadcasted
+pub struct Unsuccessful<D: Displayable> {
+    exit_status: ExitStatus,
+    reason: String,
+    result: D,
+}
+impl<D: Displayable> Unsuccessful<D> {
+    pub fn new(exit_status: ExitStatus, reason: impl Into<String>, result: D) -> Self {
+        Self {
+            exit_status,
+            reason: reason.into(),
+            result,
+        }
+    }
+}
+impl Unsuccessful<&'static str> {
+    /// The user refused to go on when asked for a confirmation
+    pub fn cancelled(message: &'static str) -> Self {
+        Self::new(ExitStatus::Cancelled, message, message)
+    }
+}
+impl<D: Displayable> Displayable for Unsuccessful<D> {
+    fn text(&self) -> String {
+        self.result.text()
+    }
+    fn json(&self) -> Value {
+        self.result.json()
+    }
+    fn table(&self) -> String {
+        self.result.table()
+    }
+    fn exit_status(&self) -> ExitStatus {
+        self.exit_status
+    }
+    fn display(&self) {
+        match output_format() {
+            OutputFormat::Text | OutputFormat::Table => self.result.display(),
+            OutputFormat::Json => println!(
+                "{}",
+                json!({
+                    "ok": false,
+                    "exit_code": self.exit_status.code(),
+                    "error": self.reason,
+                    "result": self.result.json(),
+                })
+            ),
+        }
+    }
+}
+
+/// Print a message that is not the result of the command (summaries, prompts, instructions...)
+///
+/// With [OutputFormat::Json], it goes to stderr so that stdout only contains the JSON envelope.
+pub fn interactive_println(message: &str) {
+    match output_format() {
+        OutputFormat::Text | OutputFormat::Table => print_stdout(message),
+        OutputFormat::Json => {
+            if stderr().is_terminal() {
+                eprintln!("{message}")
+            } else {
+                eprintln!("{}", strip_ansi(message))
+            }
+        }
+    }
+}
+
+fn print_stdout(s: &str) {
+    if stdout().is_terminal() {
+        println!("{s}")
+    } else {
+        println!("{}", strip_ansi(s))
+    }
+}
+
+/// Remove the ANSI escape sequences (CSI sequences such as `\x1b[1m`) of a string
+pub fn strip_ansi(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.peek() == Some(&'[') {
+            chars.next();
+            // Parameters and intermediate bytes, up to the final byte (@ to ~)
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
+
+fn json_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        _ => value.to_string(),
+    }
+}
+
+fn format_table(headers: &[String], rows: &[Vec<String>]) -> String {
+    let mut widths = headers
+        .iter()
+        .map(|h| h.chars().count())
+        .collect::<Vec<_>>();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+    let format_row = |cells: &[String]| {
+        cells
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_owned()
+    };
+    let mut lines = Vec::with_capacity(rows.len() + 2);
+    lines.push(format!("\x1b[1m{}\x1b[0m", format_row(headers)));
+    lines.push(
+        widths
+            .iter()
+            .map(|w| "-".repeat(*w))
+            .collect::<Vec<_>>()
+            .join("-+-"),
+    );
+    lines.extend(rows.iter().map(|row| format_row(row)));
+    lines.join("\n")
+}
+
+/// Build a table from a JSON value if it has a tabular shape:
+/// an array of objects (one row per object) or an object (one row per field)
+fn json_to_table(value: &Value) -> Option<String> {
+    match value {
+        Value::Array(items) if !items.is_empty() && items.iter().all(Value::is_object) => {
+            let mut headers: Vec<String> = vec![];
+            for item in items {
+                for key in item.as_object().expect("verified").keys() {
+                    if !headers.contains(key) {
+                        headers.push(key.clone());
+                    }
+                }
+            }
+            let rows = items
+                .iter()
+                .map(|item| {
+                    headers
+                        .iter()
+                        .map(|h| item.get(h).map(json_cell).unwrap_or_default())
+                        .collect()
+                })
+                .collect::<Vec<_>>();
+            Some(format_table(&headers, &rows))
+        }
+        Value::Object(fields) if !fields.is_empty() => {
+            let rows = fields
+                .iter()
+                .map(|(k, v)| vec![k.clone(), json_cell(v)])
+                .collect::<Vec<_>>();
+            Some(format_table(
+                &["field".to_owned(), "value".to_owned()],
+                &rows,
+            ))
+        }
+        _ => None,
+    }
 }
 
 impl Displayable for () {
-    fn display(&self) {}
+    fn text(&self) -> String {
+        String::new()
+    }
+    fn json(&self) -> Value {
+        Value::Null
+    }
+}
+
+impl Displayable for Box<dyn Displayable> {
+    fn text(&self) -> String {
+        (**self).text()
+    }
+    fn json(&self) -> Value {
+        (**self).json()
+    }
+    fn table(&self) -> String {
+        (**self).table()
+    }
+    fn exit_status(&self) -> ExitStatus {
+        (**self).exit_status()
+    }
+    fn display(&self) {
+        (**self).display()
+    }
 }
 
 macro_rules! str_display {
     (Vec<$name:ty>) => {
         impl Displayable for Vec<$name> {
-            fn display(&self) {
-                for e in self {
-                    println!("{e}")
-                }
+            fn text(&self) -> String {
+                self.iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+            fn json(&self) -> Value {
+                Value::Array(self.iter().map(|e| Value::String(e.to_string())).collect())
+            }
+            fn table(&self) -> String {
+                self.text()
             }
         }
     };
     ($name:ty) => {
         impl Displayable for $name {
-            fn display(&self) {
-                println!("{self}")
+            fn text(&self) -> String {
+                self.to_string()
+            }
+            fn json(&self) -> Value {
+                Value::String(self.to_string())
             }
         }
     };
@@ -51,12 +341,13 @@ str_display!(Vec<WalletAddress>);
 pub trait SerdeDisplay: serde::Serialize {}
 
 impl<T: SerdeDisplay> Displayable for T {
-    fn display(&self) {
-        println!(
-            "{}",
-            serde_json::to_string_pretty(self)
-                .expect("Caller responsability to ensure Json serialization works")
-        )
+    fn text(&self) -> String {
+        serde_json::to_string_pretty(self)
+            .expect("Caller responsability to ensure Json serialization works")
+    }
+    fn json(&self) -> Value {
+        serde_json::to_value(self)
+            .expect("Caller responsability to ensure Json serialization works")
     }
 }
 impl<T: SerdeDisplay> SerdeDisplay for Vec<T> {}
@@ -93,14 +384,54 @@ where
 }
 
 impl Displayable for WalletPolicy {
-    fn display(&self) {
-        println!(" \x1b[1mAccount name\x1b[0m: {}", self.name);
-        println!("\x1b[1mWallet policy\x1b[0m: {}", self.descriptor_template);
+    fn text(&self) -> String {
+        let mut lines = vec![
+            format!(" \x1b[1mAccount name\x1b[0m: {}", self.name),
+            format!("\x1b[1mWallet policy\x1b[0m: {}", self.descriptor_template),
+        ];
         for (i, key) in self.keys.iter().enumerate() {
             // There will never be more than 100keys in a template
             let i_len = if i < 10 { 1usize } else { 2usize };
             let left_pad_len = 8 - i_len;
-            println!("{:>left_pad_len$}\x1b[1mKey @{i}\x1b[0m: {}", "", key);
+            lines.push(format!(
+                "{:>left_pad_len$}\x1b[1mKey @{i}\x1b[0m: {}",
+                "", key
+            ));
         }
+        lines.join("\n")
+    }
+    fn json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "descriptor_template": self.descriptor_template.to_string(),
+            "keys": self.keys.iter().map(|k| k.to_string()).collect::<Vec<_>>(),
+        })
+    }
+    fn table(&self) -> String {
+        self.text()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_ansi_removes_csi_sequences() {
+        assert_eq!(strip_ansi("\x1b[1mFee\x1b[0m: 10 sat"), "Fee: 10 sat");
+        assert_eq!(strip_ansi("\x1b[38;5;208mwarning\x1b[0m"), "warning");
+    }
+
+    #[test]
+    fn strip_ansi_keeps_plain_text() {
+        assert_eq!(strip_ansi(""), "");
+        assert_eq!(strip_ansi("no escape [1m here"), "no escape [1m here");
+        // A lone escape character is not a CSI sequence
+        assert_eq!(strip_ansi("a\x1bb"), "a\x1bb");
+    }
+
+    #[test]
+    fn strip_ansi_handles_unterminated_sequence() {
+        assert_eq!(strip_ansi("text\x1b[1"), "text");
     }
 }
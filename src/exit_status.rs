@@ -0,0 +1,177 @@
+use btc_heritage_wallet::{errors::Error, heritage_service_api_client::Error as ApiError};
+
+/// The exit status of the CLI process
+///
+/// The numeric values are part of the CLI interface and MUST NOT change.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitStatus {
+    /// The command succeeded
+    #[default]
+    Success = 0,
+    /// The command failed for a reason that has no dedicated exit status
+    Failure = 1,
+    /// The command is not usable as specified (same value as clap usage errors)
+    Usage = 2,
+    /// The command needs an authentication with the Heritage service (see "service login")
+    AuthRequired = 3,
+    /// The Heritage service or the Blockchain provider could not be reached
+    ProviderUnreachable = 4,
+    /// The wallet does not have enough funds to create the transaction
+    InsufficientFunds = 5,
+    /// The user answered "no" to a confirmation prompt
+    Cancelled = 6,
+    /// The PSBT could not be signed
+    SigningFailed = 7,
+    /// The PSBT could not be broadcasted
+    BroadcastFailed = 8,
+}
+
+impl ExitStatus {
+    pub fn is_success(self) -> bool {
+        self == ExitStatus::Success
+    }
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+}
+
+impl From<ExitStatus> for std::process::ExitCode {
+    fn from(value: ExitStatus) -> Self {
+        std::process::ExitCode::from(value.code())
+    }
+}
+
+impl From<&Error> for ExitStatus {
+    fn from(value: &Error) -> Self {
+        match value {
+            Error::UninitializedServiceClient => ExitStatus::AuthRequired,
+            Error::IncorrectOnlineWallet(_)
+            | Error::IncorrectKeyProvider(_)
+            | Error::IncorrectHeritageProvider(_)
+            | Error::InvalidAddressNetwork(_) => ExitStatus::Usage,
+            Error::HeritageApiClientError(api_error) => ExitStatus::from(api_error),
+            // The other errors wrap errors of the underlying libraries (blockchain providers, BDK)
+            // as text only
+            _ => ExitStatus::from_library_message(&value.to_string()),
+        }
+    }
+}
+
+/// Wordings of the underlying libraries meaning the wallet cannot fund the transaction
+const INSUFFICIENT_FUNDS_PATTERNS: &[&str] = &["insufficient funds"];
+/// Wordings of the underlying libraries (reqwest, electrum, bitcoincore-rpc) meaning the
+/// provider could not be reached
+const UNREACHABLE_PATTERNS: &[&str] = &[
+    "connection refused",
+    "error sending request",
+    "operation timed out",
+    "dns error",
+];
+
+impl ExitStatus {
+    /// Derive the exit status of an error only available as text
+    ///
+    /// This is the only place where the exit status relies on the wording of an error:
+    /// it is the fallback for the errors of the underlying libraries that the wallet
+    /// wraps as strings. Every pattern is matched case-insensitively.
+    fn from_library_message(message: &str) -> ExitStatus {
+        let message = message.to_lowercase();
+        let matches = |patterns: &[&str]| patterns.iter().any(|p| message.contains(p));
+        if matches(INSUFFICIENT_FUNDS_PATTERNS) {
+            ExitStatus::InsufficientFunds
+        } else if matches(UNREACHABLE_PATTERNS) {
+            ExitStatus::ProviderUnreachable
+        } else {
+            ExitStatus::Failure
+        }
+    }
+}
+
+impl From<&ApiError> for ExitStatus {
+    fn from(value: &ApiError) -> Self {
+        match value {
+            ApiError::ApiErrorResponse { code: 401, .. } => ExitStatus::AuthRequired,
+            ApiError::ReqwestError(e) if e.is_connect() || e.is_timeout() => {
+                ExitStatus::ProviderUnreachable
+            }
+            _ => ExitStatus::Failure,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_status_from_error_variants() {
+        assert_eq!(
+            ExitStatus::from(&Error::UninitializedServiceClient),
+            ExitStatus::AuthRequired
+        );
+        assert_eq!(
+            ExitStatus::from(&Error::IncorrectOnlineWallet("service")),
+            ExitStatus::Usage
+        );
+        assert_eq!(
+            ExitStatus::from(&Error::IncorrectKeyProvider("Local")),
+            ExitStatus::Usage
+        );
+        assert_eq!(
+            ExitStatus::from(&Error::IncorrectHeritageProvider("Local")),
+            ExitStatus::Usage
+        );
+        assert_eq!(
+            ExitStatus::from(&Error::InvalidAddressNetwork("testnet".to_owned())),
+            ExitStatus::Usage
+        );
+        assert_eq!(
+            ExitStatus::from(&Error::Generic("Unknown wallet".to_owned())),
+            ExitStatus::Failure
+        );
+    }
+
+    #[test]
+    fn exit_status_from_insufficient_funds_message() {
+        for pattern in INSUFFICIENT_FUNDS_PATTERNS {
+            let error = Error::Generic(format!(
+                "BDK error: {} 1000 sat needed",
+                pattern.to_uppercase()
+            ));
+            assert_eq!(
+                ExitStatus::from(&error),
+                ExitStatus::InsufficientFunds,
+                "{pattern}"
+            );
+        }
+    }
+
+    #[test]
+    fn exit_status_from_unreachable_message() {
+        for pattern in UNREACHABLE_PATTERNS {
+            let error = Error::Generic(format!(
+                "Blockchain provider error: {pattern} (os error 111)"
+            ));
+            assert_eq!(
+                ExitStatus::from(&error),
+                ExitStatus::ProviderUnreachable,
+                "{pattern}"
+            );
+        }
+    }
+
+    #[test]
+    fn exit_status_codes() {
+        assert_eq!(ExitStatus::Success.code(), 0);
+        assert_eq!(ExitStatus::Usage.code(), 2);
+        assert_eq!(ExitStatus::AuthRequired.code(), 3);
+        assert_eq!(ExitStatus::ProviderUnreachable.code(), 4);
+        assert_eq!(ExitStatus::InsufficientFunds.code(), 5);
+        assert_eq!(ExitStatus::Cancelled.code(), 6);
+        assert_eq!(ExitStatus::SigningFailed.code(), 7);
+        assert_eq!(ExitStatus::BroadcastFailed.code(), 8);
+        assert!(ExitStatus::Success.is_success());
+        assert!(!ExitStatus::Failure.is_success());
+    }
+}
@@ -0,0 +1,388 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use btc_heritage_wallet::{
+    bitcoin::{psbt::Psbt, Address, Amount, OutPoint, TxOut},
+    errors::{Error, Result},
+};
+
+use crate::psbt_analysis::estimated_vsize;
+
+/// The dust limit of P2TR outputs
+pub(crate) const DUST_LIMIT: u64 = 330;
+
+/// How the fee of an inheritance spending is computed
+#[derive(Debug, Clone, Copy)]
+pub enum FeeOverride {
+    /// A fee rate, in sat/vB
+    Rate(f32),
+    /// An absolute fee
+    Absolute(Amount),
+}
+
+/// The shape of an inheritance spending, when it differs from the default of the
+/// heritage provider: every heritage UTXO drained to a single address
+#[derive(Debug, Clone, Default)]
+pub struct InheritanceSpending {
+    /// Recipients receiving a fixed amount
+    pub recipients: Vec<(Address, Amount)>,
+    pub fee: Option<FeeOverride>,
+    /// If not empty, spend only those heritage UTXOs
+    pub include: Vec<OutPoint>,
+    /// Do not spend those heritage UTXOs
+    pub exclude: Vec<OutPoint>,
+}
+
+impl InheritanceSpending {
+    /// Whether the PSBT of the heritage provider must be reshaped
+    pub fn is_default(&self) -> bool {
+        self.recipients.is_empty()
+            && self.fee.is_none()
+            && self.include.is_empty()
+            && self.exclude.is_empty()
+    }
+
+    /// Reshape a PSBT created by the heritage provider, which drains every heritage UTXO
+    /// to a single output, according to this spending.
+    ///
+    /// The single output of the PSBT stays the drain target, receiving what remains after
+    /// paying the recipients and the fee. Without fee override, the fee rate of the
+    /// heritage provider is preserved.
+    pub fn apply(&self, psbt: &mut Psbt) -> Result<()> {
+        if psbt.unsigned_tx.output.len() != 1 {
+            return Err(Error::Generic(
+                "The inheritance PSBT is expected to have a single output".to_owned(),
+            ));
+        }
+        let provider_fee_rate =
+            psbt.fee().map_err(Error::generic)?.to_sat() as f64 / estimated_vsize(psbt) as f64;
+
+        self.select_inputs(psbt)?;
+
+        // Recipients first, the drain output last
+        let drain_output = psbt.outputs.remove(0);
+        let drain_txout = psbt.unsigned_tx.output.remove(0);
+        for (address, amount) in &self.recipients {
+            psbt.unsigned_tx.output.push(TxOut {
+                value: amount.to_sat(),
+                script_pubkey: address.script_pubkey(),
+            });
+            psbt.outputs.push(Default::default());
+        }
+        psbt.unsigned_tx.output.push(drain_txout);
+        psbt.outputs.push(drain_output);
+
+        let fee = match self.fee {
+            Some(FeeOverride::Absolute(amount)) => amount.to_sat(),
+            Some(FeeOverride::Rate(rate)) => {
+                (rate as f64 * estimated_vsize(psbt) as f64).ceil() as u64
+            }
+            None => (provider_fee_rate * estimated_vsize(psbt) as f64).ceil() as u64,
+        };
+        let total_in = psbt
+            .inputs
+            .iter()
+            .map(|input| {
+                input
+                    .witness_utxo
+                    .as_ref()
+                    .map(|txout| txout.value)
+                    .ok_or_else(|| Error::Generic("The PSBT is missing an UTXO".to_owned()))
+            })
+            .sum::<Result<u64>>()?;
+        let total_recipients = self
+            .recipients
+            .iter()
+            .map(|(_, amount)| amount.to_sat())
+            .sum::<u64>();
+        let drain = total_in
+            .checked_sub(total_recipients + fee)
+            .filter(|drain| *drain >= DUST_LIMIT)
+            .ok_or_else(|| {
+                Error::Generic(format!(
+                    "The inheritance ({total_in} sat) cannot pay {total_recipients} sat \
+                    to the recipients and a {fee} sat fee"
+                ))
+            })?;
+        psbt.unsigned_tx
+            .output
+            .last_mut()
+            .expect("there is the drain output")
+            .value = drain;
+        Ok(())
+    }
+
+    /// Remove the inputs that are not selected
+    fn select_inputs(&self, psbt: &mut Psbt) -> Result<()> {
+        let available = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect::<HashSet<_>>();
+        if let Some(unknown) = self
+            .include
+            .iter()
+            .chain(self.exclude.iter())
+            .find(|outpoint| !available.contains(*outpoint))
+        {
+            return Err(Error::Generic(format!(
+                "{unknown} is not an heritage UTXO of the inheritance"
+            )));
+        }
+        let keep = |outpoint: &OutPoint| {
+            (self.include.is_empty() || self.include.contains(outpoint))
+                && !self.exclude.contains(outpoint)
+        };
+        let (txins, inputs): (Vec<_>, Vec<_>) = psbt
+            .unsigned_tx
+            .input
+            .drain(..)
+            .zip(psbt.inputs.drain(..))
+            .filter(|(txin, _)| keep(&txin.previous_output))
+            .unzip();
+        if txins.is_empty() {
+            return Err(Error::Generic("No heritage UTXO left to spend".to_owned()));
+        }
+        psbt.unsigned_tx.input = txins;
+        psbt.inputs = inputs;
+        Ok(())
+    }
+}
+
+/// The PSBTs of several inheritances, merged into a single transaction
+#[derive(Debug, Clone)]
+pub struct MergedInheritances {
+    pub psbt: Psbt,
+    /// The heritage UTXOs of each inheritance
+    inheritances: Vec<(String, Vec<OutPoint>)>,
+}
+
+impl MergedInheritances {
+    /// Merge PSBTs created by the heritage provider, each draining an inheritance to the same
+    /// address, into a single PSBT. The highest fee rate of the PSBTs is used for the result.
+    pub fn merge(psbts: Vec<(String, Psbt)>) -> Result<Self> {
+        let mut psbts = psbts.into_iter();
+        let (first_id, mut psbt) = psbts
+            .next()
+            .ok_or_else(|| Error::Generic("No inheritance to spend".to_owned()))?;
+        let fee_rate = |psbt: &Psbt| -> Result<f64> {
+            if psbt.unsigned_tx.output.len() != 1 {
+                return Err(Error::Generic(
+                    "The inheritance PSBT is expected to have a single output".to_owned(),
+                ));
+            }
+            Ok(psbt.fee().map_err(Error::generic)?.to_sat() as f64 / estimated_vsize(psbt) as f64)
+        };
+        let mut max_fee_rate = fee_rate(&psbt)?;
+        let outpoints = |psbt: &Psbt| {
+            psbt.unsigned_tx
+                .input
+                .iter()
+                .map(|txin| txin.previous_output)
+                .collect::<Vec<_>>()
+        };
+        let mut inheritances = vec![(first_id, outpoints(&psbt))];
+        let mut spent = outpoints(&psbt).into_iter().collect::<HashSet<_>>();
+
+        for (id, other) in psbts {
+            max_fee_rate = max_fee_rate.max(fee_rate(&other)?);
+            if other.unsigned_tx.output[0].script_pubkey != psbt.unsigned_tx.output[0].script_pubkey
+            {
+                return Err(Error::Generic(
+                    "The inheritance PSBTs do not have the same recipient".to_owned(),
+                ));
+            }
+            // Heir timelocks are timestamps, the greatest lock time satisfies all of them
+            if other.unsigned_tx.lock_time.is_block_time()
+                != psbt.unsigned_tx.lock_time.is_block_time()
+            {
+                return Err(Error::Generic(format!(
+                    "Inheritance {id} cannot be spent in the same transaction as the others"
+                )));
+            }
+            if other.unsigned_tx.lock_time.to_consensus_u32()
+                > psbt.unsigned_tx.lock_time.to_consensus_u32()
+            {
+                psbt.unsigned_tx.lock_time = other.unsigned_tx.lock_time;
+            }
+            psbt.unsigned_tx.version = psbt.unsigned_tx.version.max(other.unsigned_tx.version);
+            if let Some(outpoint) = outpoints(&other)
+                .into_iter()
+                .find(|outpoint| !spent.insert(*outpoint))
+            {
+                return Err(Error::Generic(format!(
+                    "Inheritance {id} spends {outpoint} which is already spent by another inheritance"
+                )));
+            }
+            merge_map(&mut psbt.xpub, other.xpub, "extended public key")?;
+            merge_map(&mut psbt.proprietary, other.proprietary, "proprietary")?;
+            merge_map(&mut psbt.unknown, other.unknown, "unknown")?;
+            inheritances.push((id, outpoints(&other)));
+            psbt.unsigned_tx.input.extend(other.unsigned_tx.input);
+            psbt.inputs.extend(other.inputs);
+        }
+
+        let total_in = psbt
+            .inputs
+            .iter()
+            .map(|input| {
+                input
+                    .witness_utxo
+                    .as_ref()
+                    .map(|txout| txout.value)
+                    .ok_or_else(|| Error::Generic("The PSBT is missing an UTXO".to_owned()))
+            })
+            .sum::<Result<u64>>()?;
+        let fee = (max_fee_rate * estimated_vsize(&psbt) as f64).ceil() as u64;
+        psbt.unsigned_tx.output[0].value = total_in.checked_sub(fee).ok_or_else(|| {
+            Error::Generic(format!(
+                "The inheritances ({total_in} sat) cannot pay a {fee} sat fee"
+            ))
+        })?;
+        Ok(Self { psbt, inheritances })
+    }
+
+    /// A human-readable breakdown of the inputs of the PSBT per inheritance
+    pub fn breakdown(&self) -> String {
+        let amounts = self
+            .psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .zip(self.psbt.inputs.iter())
+            .map(|(txin, input)| {
+                (
+                    txin.previous_output,
+                    input.witness_utxo.as_ref().map(|txout| txout.value),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let mut lines = vec!["\x1b[1mInheritances spent\x1b[0m:".to_owned()];
+        for (id, outpoints) in &self.inheritances {
+            let spent = outpoints
+                .iter()
+                .filter_map(|outpoint| amounts.get(outpoint))
+                .collect::<Vec<_>>();
+            if spent.is_empty() {
+                continue;
+            }
+            lines.push(format!(
+                "  {id}: {} UTXO(s), {} sat",
+                spent.len(),
+                spent
+                    .iter()
+                    .map(|amount| amount.unwrap_or_default())
+                    .sum::<u64>()
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Merge a global map of a PSBT into the one of another, failing if they have different values
+/// for the same key
+fn merge_map<K: Ord + core::fmt::Debug, V: PartialEq>(
+    map: &mut BTreeMap<K, V>,
+    other: BTreeMap<K, V>,
+    kind: &str,
+) -> Result<()> {
+    for (key, value) in other {
+        match map.get(&key) {
+            Some(existing) if *existing != value => {
+                return Err(Error::Generic(format!(
+                    "The inheritance PSBTs have conflicting {kind} entries for {key:?}"
+                )));
+            }
+            Some(_) => (),
+            None => {
+                map.insert(key, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use btc_heritage_wallet::bitcoin::{
+        absolute::LockTime, Network, ScriptBuf, Sequence, Transaction, TxIn, Witness,
+    };
+
+    use super::*;
+
+    fn p2tr_script(byte: u8) -> ScriptBuf {
+        ScriptBuf::from_bytes([&[0x51, 0x20][..], &[byte; 32]].concat())
+    }
+
+    /// A PSBT draining a 10000 sat heritage UTXO to a single output with a 1000 sat fee
+    fn inheritance_psbt() -> Psbt {
+        let mut psbt = Psbt::from_unsigned_tx(Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: 9_000,
+                script_pubkey: p2tr_script(1),
+            }],
+        })
+        .unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 10_000,
+            script_pubkey: p2tr_script(0),
+        });
+        psbt
+    }
+
+    fn spending(recipient_amount: u64, fee: u64) -> InheritanceSpending {
+        InheritanceSpending {
+            recipients: vec![(
+                Address::from_script(&p2tr_script(2), Network::Bitcoin).unwrap(),
+                Amount::from_sat(recipient_amount),
+            )],
+            fee: Some(FeeOverride::Absolute(Amount::from_sat(fee))),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn apply_drains_the_remaining_to_the_last_output() {
+        let mut psbt = inheritance_psbt();
+        spending(5_000, 500).apply(&mut psbt).unwrap();
+        let values = psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .map(|txout| (txout.script_pubkey.clone(), txout.value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![(p2tr_script(2), 5_000), (p2tr_script(1), 4_500)]
+        );
+        assert_eq!(psbt.outputs.len(), 2);
+        assert_eq!(psbt.fee().unwrap().to_sat(), 500);
+    }
+
+    #[test]
+    fn apply_accepts_a_drain_at_the_dust_limit() {
+        let mut psbt = inheritance_psbt();
+        spending(10_000 - 500 - DUST_LIMIT, 500)
+            .apply(&mut psbt)
+            .unwrap();
+        assert_eq!(psbt.unsigned_tx.output[1].value, DUST_LIMIT);
+    }
+
+    #[test]
+    fn apply_rejects_a_drain_below_the_dust_limit() {
+        let mut psbt = inheritance_psbt();
+        assert!(spending(10_000 - 500 - DUST_LIMIT + 1, 500)
+            .apply(&mut psbt)
+            .is_err());
+        let mut psbt = inheritance_psbt();
+        assert!(spending(10_000, 500).apply(&mut psbt).is_err());
+    }
+}
@@ -1,5 +1,9 @@
 mod commands;
 mod display;
+mod exit_status;
+mod inheritance_psbt;
+mod psbt_analysis;
+mod psbt_io;
 mod spendflow;
 mod utils;
 
@@ -7,7 +11,7 @@ use clap::Parser;
 use commands::CliParser;
 
 #[tokio::main]
-async fn main() {
+async fn main() -> std::process::ExitCode {
     env_logger::Builder::from_env(
         env_logger::Env::default().default_filter_or("error,tracing::span=warn"),
     )
@@ -16,8 +20,6 @@ async fn main() {
 
     let cli_parser = CliParser::parse();
     log::debug!("Processing {:?}", cli_parser);
-    match cli_parser.execute().await {
-        Ok(displayable) => displayable.display(),
-        Err(e) => log::error!("{e}"),
-    };
+    display::set_output_format(cli_parser.gargs.output);
+    display::render(cli_parser.execute().await).into()
 }
@@ -0,0 +1,672 @@
+use std::collections::HashMap;
+
+use btc_heritage_wallet::{
+    bitcoin::{
+        absolute::LockTime,
+        opcodes::all::{OP_CLTV, OP_CSV, OP_PUSHNUM_1, OP_PUSHNUM_16},
+        psbt::{Input, Output, Psbt},
+        script::{read_scriptint, Instruction},
+        secp256k1::XOnlyPublicKey,
+        taproot::TapLeafHash,
+        Address, Network, Script,
+    },
+    heritage_service_api_client::Fingerprint,
+    PsbtSummary,
+};
+use chrono::DateTime;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::display::Displayable;
+
+/// Limits above which the fee of a PSBT is considered suspicious
+#[derive(Debug, Clone, Copy, clap::Args)]
+pub struct FeeLimits {
+    /// Warn if the fee is more than this percentage of the amount sent to external addresses
+    #[arg(long, value_name = "PERCENT", default_value_t = FeeLimits::default().max_fee_percent)]
+    pub max_fee_percent: f64,
+    /// Warn if the estimated fee rate is more than this multiple of the current fee rate estimate
+    #[arg(long, value_name = "MULTIPLE", default_value_t = FeeLimits::default().max_fee_rate_multiple)]
+    pub max_fee_rate_multiple: f64,
+    /// Warn if the estimated fee rate, in sat/vB, is above this value.
+    /// {n}Only used when the current fee rate estimate is not available
+    #[arg(long, value_name = "SAT/VB", default_value_t = FeeLimits::default().max_fee_rate)]
+    pub max_fee_rate: f64,
+    /// The current fee rate estimate, in sat/vB, if known
+    #[arg(skip)]
+    pub current_fee_rate: Option<f64>,
+}
+impl Default for FeeLimits {
+    fn default() -> Self {
+        Self {
+            max_fee_percent: 5.0,
+            max_fee_rate_multiple: 3.0,
+            max_fee_rate: 200.0,
+            current_fee_rate: None,
+        }
+    }
+}
+
+/// How an input of the PSBT is spent
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SpendPath {
+    /// The owner key path, the usual way to spend from an Heritage wallet
+    OwnerKeyPath { owner: Vec<String> },
+    /// An Heir script path, only usable after the Heir timelock
+    HeirScriptPath {
+        heir: Vec<String>,
+        heir_fingerprint: Option<String>,
+        /// The absolute timelock (timestamp) of the script
+        locked_until: Option<u32>,
+        /// The relative timelock (blocks) of the script
+        relative_lock_blocks: Option<u32>,
+    },
+    /// Not a Taproot input, or not enough information in the PSBT
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InputAnalysis {
+    pub outpoint: String,
+    pub amount: Option<u64>,
+    pub spend_path: SpendPath,
+    /// The label of the UTXO, if any
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputAnalysis {
+    pub address: Option<String>,
+    pub amount: u64,
+    /// The local wallets the output returns to, empty if it is an external output
+    pub owners: Vec<String>,
+    /// The name of the contact of the address book with this address, if any
+    pub contact: Option<String>,
+    /// The label of the address, if any
+    pub label: Option<String>,
+}
+impl OutputAnalysis {
+    pub fn is_change(&self) -> bool {
+        !self.owners.is_empty()
+    }
+}
+
+/// An analysis of a PSBT with sanity checks on its fee, the identification of the outputs
+/// returning to local wallets and of the spending path of each input
+#[derive(Debug, Clone, Serialize)]
+pub struct PsbtAnalysis {
+    pub inputs: Vec<InputAnalysis>,
+    pub outputs: Vec<OutputAnalysis>,
+    pub fee: Option<u64>,
+    /// The fee rate in sat/vB, using an estimation of the final size of the transaction
+    pub estimated_fee_rate: Option<f64>,
+    pub warnings: Vec<String>,
+}
+
+impl PsbtAnalysis {
+    pub fn new(
+        psbt: &Psbt,
+        fingerprints: Option<&HashMap<Fingerprint, Vec<String>>>,
+        network: Network,
+        fee_limits: &FeeLimits,
+    ) -> Self {
+        let empty = HashMap::new();
+        let fingerprints = fingerprints.unwrap_or(&empty);
+        let names_of = |fg: &Fingerprint| fingerprints.get(fg).cloned().unwrap_or_default();
+
+        let mut warnings = vec![];
+
+        let inputs = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .zip(psbt.inputs.iter())
+            .map(|(txin, input)| InputAnalysis {
+                outpoint: txin.previous_output.to_string(),
+                amount: input.witness_utxo.as_ref().map(|txout| txout.value),
+                spend_path: spend_path(input, psbt.unsigned_tx.lock_time, &names_of),
+                label: None,
+            })
+            .collect::<Vec<_>>();
+
+        let outputs = psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .zip(psbt.outputs.iter())
+            .map(|(txout, output)| OutputAnalysis {
+                address: Address::from_script(&txout.script_pubkey, network)
+                    .ok()
+                    .map(|a| a.to_string()),
+                amount: txout.value,
+                owners: output_owners(output, &names_of),
+                contact: None,
+                label: None,
+            })
+            .collect::<Vec<_>>();
+
+        for (i, input) in inputs.iter().enumerate() {
+            match &input.spend_path {
+                SpendPath::Unknown => warnings.push(format!(
+                    "Cannot identify how input #{} ({}) is spent",
+                    i + 1,
+                    input.outpoint
+                )),
+                SpendPath::HeirScriptPath {
+                    locked_until: Some(ts),
+                    ..
+                } if *ts as i64 > chrono::Utc::now().timestamp() => warnings.push(format!(
+                    "Input #{} uses an Heir script path that is locked until {}, \
+                    the transaction cannot be mined before",
+                    i + 1,
+                    format_ts(*ts)
+                )),
+                _ => (),
+            }
+        }
+
+        let fee = psbt.fee().ok().map(|fee| fee.to_sat());
+        let vsize = vsize_with_spend_paths(psbt, inputs.iter().map(|i| &i.spend_path));
+        let estimated_fee_rate = fee.map(|fee| fee as f64 / vsize as f64);
+        match (fee, estimated_fee_rate) {
+            (Some(fee), Some(fee_rate)) => {
+                let external_amount = outputs
+                    .iter()
+                    .filter(|o| !o.is_change())
+                    .map(|o| o.amount)
+                    .sum::<u64>();
+                // For a transfer between local wallets, compare with everything that is moved
+                let reference_amount = if external_amount > 0 {
+                    external_amount
+                } else {
+                    outputs.iter().map(|o| o.amount).sum()
+                };
+                let fee_percent = 100.0 * fee as f64 / reference_amount.max(1) as f64;
+                if fee_percent > fee_limits.max_fee_percent {
+                    warnings.push(format!(
+                        "The fee ({fee} sat) is {fee_percent:.1}% of the amount sent \
+                        ({reference_amount} sat), above the {}% limit",
+                        fee_limits.max_fee_percent
+                    ));
+                }
+                match fee_limits.current_fee_rate {
+                    Some(current_fee_rate) if current_fee_rate > 0.0 => {
+                        if fee_rate > current_fee_rate * fee_limits.max_fee_rate_multiple {
+                            warnings.push(format!(
+                                "The fee rate (~{fee_rate:.1} sat/vB) is more than {} times \
+                                the current fee rate estimate (~{current_fee_rate:.1} sat/vB)",
+                                fee_limits.max_fee_rate_multiple
+                            ));
+                        }
+                    }
+                    _ => {
+                        if fee_rate > fee_limits.max_fee_rate {
+                            warnings.push(format!(
+                                "The fee rate (~{fee_rate:.1} sat/vB) is above the {} sat/vB limit",
+                                fee_limits.max_fee_rate
+                            ));
+                        }
+                    }
+                }
+            }
+            _ => warnings.push(
+                "Cannot compute the fee, the PSBT is missing the UTXO of some inputs".to_owned(),
+            ),
+        }
+
+        Self {
+            inputs,
+            outputs,
+            fee,
+            estimated_fee_rate,
+            warnings,
+        }
+    }
+
+    /// The names of the local wallets spending the inputs with their owner key path
+    pub fn spending_wallets(&self) -> Vec<&str> {
+        let mut wallets = self
+            .inputs
+            .iter()
+            .filter_map(|input| match &input.spend_path {
+                SpendPath::OwnerKeyPath { owner } => Some(owner),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|owner| owner.strip_prefix("wallet:"))
+            .collect::<Vec<_>>();
+        wallets.sort();
+        wallets.dedup();
+        wallets
+    }
+
+    /// Name the outputs paying a contact of the address book, given as address -> name
+    pub fn set_contacts(&mut self, contacts: &HashMap<String, String>) {
+        for output in self.outputs.iter_mut() {
+            output.contact = output
+                .address
+                .as_ref()
+                .and_then(|address| contacts.get(address))
+                .cloned();
+        }
+    }
+
+    /// Label the inputs and outputs, given as UTXO (<TXID>:<VOUT>) or address -> label
+    pub fn set_labels(&mut self, labels: &HashMap<String, String>) {
+        for input in self.inputs.iter_mut() {
+            input.label = labels.get(&input.outpoint).cloned();
+        }
+        for output in self.outputs.iter_mut() {
+            output.label = output
+                .address
+                .as_ref()
+                .and_then(|address| labels.get(address))
+                .cloned();
+        }
+    }
+
+    /// Add the contact name and the label next to each occurence of an input UTXO or of an
+    /// output address in the text
+    pub fn annotate(&self, text: &str) -> String {
+        // An address can be paid by several outputs, annotate it once
+        let annotations = self
+            .inputs
+            .iter()
+            .filter_map(|input| {
+                Some((
+                    input.outpoint.as_str(),
+                    annotation(None, input.label.as_deref())?,
+                ))
+            })
+            .chain(self.outputs.iter().filter_map(|output| {
+                Some((
+                    output.address.as_deref()?,
+                    annotation(output.contact.as_deref(), output.label.as_deref())?,
+                ))
+            }))
+            .collect::<HashMap<_, _>>();
+        annotate_tokens(text, &annotations)
+    }
+
+    /// The human-readable presentation of the analysis
+    pub fn text(&self) -> String {
+        let mut lines = vec!["\x1b[1mInputs\x1b[0m:".to_owned()];
+        for input in &self.inputs {
+            let path = match &input.spend_path {
+                SpendPath::OwnerKeyPath { owner } if owner.is_empty() => {
+                    "owner key path".to_owned()
+                }
+                SpendPath::OwnerKeyPath { owner } => {
+                    format!("owner key path ({})", owner.join(", "))
+                }
+                SpendPath::HeirScriptPath {
+                    heir,
+                    heir_fingerprint,
+                    locked_until,
+                    relative_lock_blocks,
+                } => {
+                    let heir = if heir.is_empty() {
+                        heir_fingerprint.clone().unwrap_or("unknown".to_owned())
+                    } else {
+                        heir.join(", ")
+                    };
+                    let mut locks = vec![];
+                    if let Some(ts) = locked_until {
+                        locks.push(format!("locked until {}", format_ts(*ts)));
+                    }
+                    if let Some(blocks) = relative_lock_blocks {
+                        locks.push(format!("{blocks} blocks after confirmation"));
+                    }
+                    format!("Heir script path ({heir}, {})", locks.join(", "))
+                }
+                SpendPath::Unknown => "unknown".to_owned(),
+            };
+            let outpoint = match annotation(None, input.label.as_deref()) {
+                Some(annotation) => format!("{} {annotation}", input.outpoint),
+                None => input.outpoint.clone(),
+            };
+            lines.push(format!("  {outpoint}: {path}"));
+        }
+        lines.push("\x1b[1mOutputs\x1b[0m:".to_owned());
+        for output in &self.outputs {
+            let address = match (
+                &output.address,
+                annotation(output.contact.as_deref(), output.label.as_deref()),
+            ) {
+                (Some(address), Some(annotation)) => format!("{address} {annotation}"),
+                (Some(address), None) => address.clone(),
+                (None, _) => "<non-standard>".to_owned(),
+            };
+            let destination = if output.is_change() {
+                format!("back to {}", output.owners.join(", "))
+            } else {
+                "external".to_owned()
+            };
+            lines.push(format!("  {address}: {} sat, {destination}", output.amount));
+        }
+        if let (Some(fee), Some(fee_rate)) = (self.fee, self.estimated_fee_rate) {
+            lines.push(format!(
+                "\x1b[1mFee\x1b[0m: {fee} sat (~{fee_rate:.1} sat/vB)"
+            ));
+        }
+        lines.extend(self.warnings_text());
+        lines.join("\n")
+    }
+
+    /// The warnings, highlighted, one per line
+    pub fn warnings_text(&self) -> Vec<String> {
+        self.warnings
+            .iter()
+            .map(|w| format!("\x1b[1;33mWARNING\x1b[0m: {w}"))
+            .collect()
+    }
+
+    /// Add the analysis to the JSON representation of a PSBT summary
+    pub fn extend_json(&self, mut summary: Value) -> Value {
+        let analysis = serde_json::to_value(self).expect("PsbtAnalysis is serializable");
+        match &mut summary {
+            Value::Object(fields) => {
+                fields.insert("analysis".to_owned(), analysis);
+                summary
+            }
+            _ => serde_json::json!({"summary": summary, "analysis": analysis}),
+        }
+    }
+}
+
+/// Append its annotation to each token of the text that is exactly an annotated reference.
+/// Addresses and outpoints are made of alphanumeric characters and ':', surrounding ':'
+/// are not part of the reference. The text is scanned once, so annotations are never
+/// themselves annotated.
+fn annotate_tokens(text: &str, annotations: &HashMap<&str, String>) -> String {
+    let is_token_char = |c: char| c.is_ascii_alphanumeric() || c == ':';
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(is_token_char) {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(|c| !is_token_char(c)).unwrap_or(rest.len());
+        let token = &rest[..end];
+        rest = &rest[end..];
+
+        let reference = token.trim_matches(':');
+        match annotations.get(reference) {
+            Some(annotation) => {
+                let leading = token.len() - token.trim_start_matches(':').len();
+                result.push_str(&token[..leading + reference.len()]);
+                result.push(' ');
+                result.push_str(annotation);
+                result.push_str(&token[leading + reference.len()..]);
+            }
+            None => result.push_str(token),
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// The contact name and the label of an input or output as "(@<CONTACT>, <LABEL>)"
+fn annotation(contact: Option<&str>, label: Option<&str>) -> Option<String> {
+    match (contact, label) {
+        (Some(contact), Some(label)) => Some(format!("(@{contact}, {label})")),
+        (Some(contact), None) => Some(format!("(@{contact})")),
+        (None, Some(label)) => Some(format!("({label})")),
+        (None, None) => None,
+    }
+}
+
+fn format_ts(ts: u32) -> String {
+    DateTime::from_timestamp(ts as i64, 0)
+        .expect("timestamp is in range")
+        .date_naive()
+        .to_string()
+}
+
+/// The names of the local wallets an output returns to, using the key origins of the PSBT
+fn output_owners(output: &Output, names_of: &impl Fn(&Fingerprint) -> Vec<String>) -> Vec<String> {
+    let mut owners = output
+        .tap_key_origins
+        .values()
+        .map(|(_, (fg, _))| fg)
+        .chain(output.bip32_derivation.values().map(|(fg, _)| fg))
+        .flat_map(names_of)
+        .collect::<Vec<_>>();
+    owners.sort();
+    owners.dedup();
+    owners
+}
+
+/// The keys and locks of an Heir script
+#[derive(Debug, Default)]
+pub(crate) struct HeirLeaf {
+    pub key: Option<XOnlyPublicKey>,
+    pub absolute_lock: Option<u32>,
+    pub relative_lock: Option<u32>,
+}
+
+impl HeirLeaf {
+    pub fn parse(script: &Script) -> Self {
+        let mut leaf = HeirLeaf::default();
+        let mut last_number = None;
+        for instruction in script.instructions().flatten() {
+            match instruction {
+                Instruction::PushBytes(bytes) => {
+                    let bytes = bytes.as_bytes();
+                    if leaf.key.is_none() && bytes.len() == 32 {
+                        leaf.key = XOnlyPublicKey::from_slice(bytes).ok();
+                    }
+                    last_number = read_scriptint(bytes).ok();
+                }
+                Instruction::Op(OP_CLTV) => leaf.absolute_lock = last_number.map(|n| n as u32),
+                Instruction::Op(OP_CSV) => leaf.relative_lock = last_number.map(|n| n as u32),
+                Instruction::Op(op)
+                    if (OP_PUSHNUM_1.to_u8()..=OP_PUSHNUM_16.to_u8()).contains(&op.to_u8()) =>
+                {
+                    last_number = Some((op.to_u8() - OP_PUSHNUM_1.to_u8() + 1) as i64);
+                }
+                Instruction::Op(_) => last_number = None,
+            }
+        }
+        leaf
+    }
+
+    /// Whether the absolute lock of this leaf is satisfied by the transaction lock time
+    fn unlocked_by(&self, lock_time: LockTime) -> bool {
+        match self.absolute_lock {
+            Some(lock) => {
+                let tx_lock = lock_time.to_consensus_u32();
+                // Both must be timestamps or both block heights
+                (lock >= 500_000_000) == (tx_lock >= 500_000_000) && lock <= tx_lock
+            }
+            None => true,
+        }
+    }
+}
+
+/// Identify how a Taproot input is (or will be) spent, first using the signatures
+/// already in the PSBT, then the lock time of the transaction
+fn spend_path(
+    input: &Input,
+    lock_time: LockTime,
+    names_of: &impl Fn(&Fingerprint) -> Vec<String>,
+) -> SpendPath {
+    let Some(internal_key) = input.tap_internal_key else {
+        return SpendPath::Unknown;
+    };
+    let owner_key_path = || SpendPath::OwnerKeyPath {
+        owner: input
+            .tap_key_origins
+            .get(&internal_key)
+            .map(|(_, (fg, _))| names_of(fg))
+            .unwrap_or_default(),
+    };
+    let heir_script_path = |leaf_hash: &TapLeafHash, leaf: HeirLeaf| {
+        let heir_fingerprint = input
+            .tap_key_origins
+            .iter()
+            .find(|(key, (leaf_hashes, _))| {
+                leaf.key.as_ref() == Some(*key) || leaf_hashes.contains(leaf_hash)
+            })
+            .map(|(_, (_, (fg, _)))| *fg);
+        SpendPath::HeirScriptPath {
+            heir: heir_fingerprint.as_ref().map(names_of).unwrap_or_default(),
+            heir_fingerprint: heir_fingerprint.map(|fg| fg.to_string()),
+            locked_until: leaf.absolute_lock,
+            relative_lock_blocks: leaf.relative_lock,
+        }
+    };
+
+    if input.tap_key_sig.is_some() {
+        return owner_key_path();
+    }
+    let leaves = input
+        .tap_scripts
+        .values()
+        .map(|(script, leaf_version)| {
+            (
+                TapLeafHash::from_script(script, *leaf_version),
+                HeirLeaf::parse(script),
+            )
+        })
+        .collect::<Vec<_>>();
+    // A script signature designates the leaf
+    if let Some(((_, signed_leaf_hash), _)) = input.tap_script_sigs.iter().next() {
+        if let Some((leaf_hash, leaf)) = leaves.into_iter().find(|(lh, _)| lh == signed_leaf_hash) {
+            return heir_script_path(&leaf_hash, leaf);
+        }
+        return SpendPath::Unknown;
+    }
+    // Without signature, only a lock time reaching an Heir timelock enables a script path.
+    // Prefer a leaf whose Heir key is known locally.
+    let (known, others): (Vec<_>, Vec<_>) = leaves
+        .into_iter()
+        .filter(|(_, leaf)| leaf.absolute_lock.is_some() && leaf.unlocked_by(lock_time))
+        .partition(|(leaf_hash, _)| {
+            input
+                .tap_key_origins
+                .values()
+                .any(|(leaf_hashes, (fg, _))| {
+                    leaf_hashes.contains(leaf_hash) && !names_of(fg).is_empty()
+                })
+        });
+    match known.into_iter().chain(others).next() {
+        Some((leaf_hash, leaf)) => heir_script_path(&leaf_hash, leaf),
+        None => owner_key_path(),
+    }
+}
+
+/// Estimate the virtual size of the transaction once all its inputs are signed
+pub fn estimated_vsize(psbt: &Psbt) -> u64 {
+    let no_names = |_: &Fingerprint| vec![];
+    let spend_paths = psbt
+        .inputs
+        .iter()
+        .map(|input| spend_path(input, psbt.unsigned_tx.lock_time, &no_names))
+        .collect::<Vec<_>>();
+    vsize_with_spend_paths(psbt, spend_paths.iter())
+}
+
+fn vsize_with_spend_paths<'a>(
+    psbt: &Psbt,
+    spend_paths: impl Iterator<Item = &'a SpendPath>,
+) -> u64 {
+    // Segwit marker and flag
+    let mut weight = psbt.unsigned_tx.weight().to_wu() + 2;
+    for (input, spend_path) in psbt.inputs.iter().zip(spend_paths) {
+        weight += match (&input.final_script_witness, spend_path) {
+            (Some(witness), _) => witness.serialized_len(),
+            // Items count, signature, script and control block, with their lengths
+            (None, SpendPath::HeirScriptPath { .. }) => input
+                .tap_scripts
+                .iter()
+                .map(|(cb, (script, _))| 1 + 1 + 64 + 1 + script.len() + 1 + cb.size())
+                .max()
+                .unwrap_or_default(),
+            // Items count, signature length and a 64 bytes signature
+            (None, _) => 1 + 1 + 64,
+        } as u64;
+    }
+    (weight + 3) / 4
+}
+
+/// A [PsbtSummary] completed with its [PsbtAnalysis]
+pub struct AnalyzedPsbtSummary {
+    pub summary: PsbtSummary,
+    pub analysis: PsbtAnalysis,
+}
+
+impl Displayable for AnalyzedPsbtSummary {
+    fn text(&self) -> String {
+        format!(
+            "{}\n{}",
+            self.analysis.annotate(&self.summary.text()),
+            self.analysis.text()
+        )
+    }
+    fn json(&self) -> Value {
+        self.analysis.extend_json(self.summary.json())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use btc_heritage_wallet::bitcoin::{
+        OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
+    };
+
+    use super::*;
+
+    /// A PSBT spending a P2TR UTXO to a single P2TR output
+    fn p2tr_psbt() -> Psbt {
+        let p2tr_script = || ScriptBuf::from_bytes([&[0x51, 0x20][..], &[0u8; 32]].concat());
+        Psbt::from_unsigned_tx(Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: 10_000,
+                script_pubkey: p2tr_script(),
+            }],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn estimated_vsize_key_path() {
+        // A 1-input 1-output P2TR key path spend is 111 vB
+        assert_eq!(estimated_vsize(&p2tr_psbt()), 111);
+    }
+
+    #[test]
+    fn estimated_vsize_uses_final_witness() {
+        let mut psbt = p2tr_psbt();
+        psbt.inputs[0].final_script_witness = Some(Witness::from_slice(&[
+            [0u8; 64].to_vec(),
+            [0u8; 10].to_vec(),
+        ]));
+        // 376 WU of transaction, 2 WU of marker and flag, 77 WU of witness
+        assert_eq!(estimated_vsize(&psbt), 114);
+    }
+
+    #[test]
+    fn annotate_tokens_matches_exact_references() {
+        let annotations = HashMap::from([
+            ("bc1qabc", "(@alice)".to_owned()),
+            ("aa:0", "(change)".to_owned()),
+        ]);
+        assert_eq!(
+            annotate_tokens("bc1qabc: 1000 sat, bc1qabcd: 10 sat", &annotations),
+            "bc1qabc (@alice): 1000 sat, bc1qabcd: 10 sat"
+        );
+        assert_eq!(
+            annotate_tokens("aa:0 aa:01 xaa:0", &annotations),
+            "aa:0 (change) aa:01 xaa:0"
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use btc_heritage_wallet::{
+    bitcoin::psbt::Psbt,
+    errors::{Error, Result},
+};
+
+use crate::display::Displayable;
+
+/// The magic bytes starting every binary PSBT (BIP174)
+const PSBT_MAGIC: &[u8] = b"psbt\xff";
+
+/// Arguments specifying where to read a Partially Signed Bitcoin Transaction (PSBT) from
+#[derive(Debug, Clone, clap::Args)]
+#[group(id = "psbt_input", required = true, multiple = false)]
+pub struct PsbtInput {
+    /// The PSBT, base64 encoded, or "-" to read it from stdin
+    #[arg(value_name = "PSBT")]
+    psbt: Option<String>,
+    /// Read the PSBT from a file, either base64 encoded or raw binary (BIP174)
+    #[arg(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    psbt_file: Option<PathBuf>,
+}
+
+impl PsbtInput {
+    pub fn load(self) -> Result<Psbt> {
+        match (self.psbt, self.psbt_file) {
+            (Some(psbt), None) => parse_psbt_arg(&psbt),
+            (None, Some(psbt_file)) => read_psbt_file(&psbt_file),
+            _ => unreachable!("clap ensures exactly one is present"),
+        }
+    }
+}
+
+/// Arguments specifying where to read multiple Partially Signed Bitcoin Transactions (PSBT) from
+#[derive(Debug, Clone, clap::Args)]
+#[group(id = "psbts_input", required = true, multiple = true)]
+pub struct PsbtsInput {
+    /// The PSBTs, base64 encoded, or "-" to read one of them from stdin
+    #[arg(value_name = "PSBT")]
+    psbts: Vec<String>,
+    /// Read a PSBT from a file, either base64 encoded or raw binary (BIP174).
+    /// {n}Can be specified multiple times.
+    #[arg(long = "psbt-file", value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    psbt_files: Vec<PathBuf>,
+}
+
+impl PsbtsInput {
+    pub fn load(self) -> Result<Vec<Psbt>> {
+        if self.psbts.iter().filter(|psbt| *psbt == "-").count() > 1 {
+            return Err(Error::Generic(
+                "Only one PSBT can be read from stdin".to_owned(),
+            ));
+        }
+        self.psbts
+            .iter()
+            .map(|psbt| parse_psbt_arg(psbt))
+            .chain(self.psbt_files.iter().map(|path| read_psbt_file(path)))
+            .collect()
+    }
+}
+
+/// Parse a PSBT command-line argument, base64 encoded or "-" for stdin
+fn parse_psbt_arg(psbt: &str) -> Result<Psbt> {
+    if psbt == "-" {
+        let mut buffer = vec![];
+        std::io::stdin().read_to_end(&mut buffer).map_err(|e| {
+            log::error!("Could not read the PSBT from stdin: {e}");
+            Error::generic(e)
+        })?;
+        parse_psbt_bytes(&buffer)
+    } else {
+        parse_psbt_base64(psbt)
+    }
+}
+
+/// Arguments specifying how to output a Partially Signed Bitcoin Transaction (PSBT)
+/// for transfers to and from air-gapped devices
+#[derive(Debug, Clone, clap::Args)]
+pub struct PsbtOutput {
+    /// Write the resulting PSBT to a file instead of displaying it.
+    /// {n}The PSBT is written as raw binary (BIP174) if the extension is .psbt, base64 encoded otherwise
+    #[arg(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    out: Option<PathBuf>,
+    /// Display the resulting PSBT as BBQr chunks, one per line, each one fitting in a QR code.
+    /// {n}Only BBQr is supported, not the UR (BC-UR) encoding
+    #[arg(long, default_value_t = false)]
+    bbqr: bool,
+    /// The maximum length of each BBQr chunk
+    #[arg(long, value_name = "LENGTH", default_value_t = 400, requires = "bbqr", value_parser = clap::value_parser!(u16).range(16..))]
+    bbqr_chunk_size: u16,
+}
+
+impl PsbtOutput {
+    /// Write or encode the PSBT as requested
+    pub fn output(&self, psbt: &Psbt) -> Result<Box<dyn Displayable>> {
+        if let Some(out) = &self.out {
+            write_psbt_file(psbt, out)?;
+        }
+        Ok(if self.bbqr {
+            Box::new(bbqr_encode(
+                &psbt.serialize(),
+                self.bbqr_chunk_size as usize,
+            )?)
+        } else if let Some(out) = &self.out {
+            Box::new(format!("PSBT written to {}", out.display()))
+        } else {
+            Box::new(psbt.to_string())
+        })
+    }
+}
+
+fn parse_psbt_base64(s: &str) -> Result<Psbt> {
+    Psbt::from_str(s.trim()).map_err(|e| {
+        log::error!("Invalid PSBT: {e}");
+        Error::generic(e)
+    })
+}
+
+/// Parse a PSBT that can either be raw binary or base64 encoded
+fn parse_psbt_bytes(bytes: &[u8]) -> Result<Psbt> {
+    if bytes.starts_with(PSBT_MAGIC) {
+        Psbt::deserialize(bytes).map_err(|e| {
+            log::error!("Invalid binary PSBT: {e}");
+            Error::generic(e)
+        })
+    } else {
+        parse_psbt_base64(&String::from_utf8_lossy(bytes))
+    }
+}
+
+fn read_psbt_file(path: &Path) -> Result<Psbt> {
+    let bytes = std::fs::read(path).map_err(|e| {
+        log::error!("Could not read {}: {e}", path.display());
+        Error::generic(e)
+    })?;
+    parse_psbt_bytes(&bytes)
+}
+
+fn write_psbt_file(psbt: &Psbt, path: &Path) -> Result<()> {
+    let content = if path.extension().is_some_and(|ext| ext == "psbt") {
+        psbt.serialize()
+    } else {
+        format!("{psbt}\n").into_bytes()
+    };
+    std::fs::write(path, content).map_err(|e| {
+        log::error!("Could not write {}: {e}", path.display());
+        Error::generic(e)
+    })
+}
+
+/// Encode a binary PSBT into BBQr chunks, using the uncompressed Base32 encoding
+/// (see https://github.com/coinkite/BBQr/blob/master/BBQr.md)
+fn bbqr_encode(data: &[u8], max_chunk_size: usize) -> Result<Vec<String>> {
+    const HEADER_LEN: usize = 8;
+    let encoded = base32_encode(data);
+    // Base32 encodes 5 bytes in 8 characters, every chunk but the last must
+    // contain a whole number of those groups
+    let data_len = ((max_chunk_size - HEADER_LEN) / 8 * 8).max(8);
+    let chunks = encoded.as_bytes().chunks(data_len).collect::<Vec<_>>();
+    let total = chunks.len();
+    // BBQr allows at most 1295 (ZZ in base36) chunks
+    if total > 1295 {
+        return Err(Error::Generic(format!(
+            "The PSBT needs {total} BBQr chunks of {max_chunk_size} characters, \
+            the maximum is 1295, use a bigger chunk size"
+        )));
+    }
+    Ok(chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            format!(
+                "B$2P{}{}{}",
+                base36_2digits(total),
+                base36_2digits(i),
+                core::str::from_utf8(chunk).expect("base32 is ASCII")
+            )
+        })
+        .collect())
+}
+
+fn base36_2digits(n: usize) -> String {
+    const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    format!(
+        "{}{}",
+        DIGITS[(n / 36) % 36] as char,
+        DIGITS[n % 36] as char
+    )
+}
+
+/// RFC 4648 Base32, without padding
+fn base32_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut res = String::with_capacity((data.len() * 8 + 4) / 5);
+    let mut buffer = 0u16;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u16;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            res.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        res.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base32_encode_rfc4648_vectors() {
+        assert_eq!(base32_encode(b""), "");
+        assert_eq!(base32_encode(b"f"), "MY");
+        assert_eq!(base32_encode(b"fo"), "MZXQ");
+        assert_eq!(base32_encode(b"foo"), "MZXW6");
+        assert_eq!(base32_encode(b"foob"), "MZXW6YQ");
+        assert_eq!(base32_encode(b"fooba"), "MZXW6YTB");
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+    }
+
+    #[test]
+    fn base36_2digits_encoding() {
+        assert_eq!(base36_2digits(0), "00");
+        assert_eq!(base36_2digits(35), "0Z");
+        assert_eq!(base36_2digits(36), "10");
+        assert_eq!(base36_2digits(1295), "ZZ");
+    }
+
+    #[test]
+    fn bbqr_encode_single_chunk() {
+        let chunks = bbqr_encode(b"foobar", 100).unwrap();
+        assert_eq!(chunks, vec!["B$2P0100MZXW6YTBOI".to_owned()]);
+    }
+
+    #[test]
+    fn bbqr_encode_chunks_are_whole_base32_groups() {
+        // 20 bytes are 32 Base32 characters, 16 fit in a 24 characters chunk
+        let data = [0u8; 20];
+        let chunks = bbqr_encode(&data, 24).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].starts_with("B$2P0200"));
+        assert!(chunks[1].starts_with("B$2P0201"));
+        assert!(chunks.iter().all(|chunk| chunk.len() == 24));
+
+        // A chunk size that is not a whole number of groups is rounded down
+        let chunks = bbqr_encode(&data, 30).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].len(), 24);
+        assert_eq!(chunks[1].len(), 24);
+    }
+
+    #[test]
+    fn bbqr_encode_too_many_chunks() {
+        // Every 5 bytes are a chunk of 8 Base32 characters
+        assert_eq!(bbqr_encode(&[0u8; 1295 * 5], 16).unwrap().len(), 1295);
+        assert!(bbqr_encode(&[0u8; 1296 * 5], 16).is_err());
+    }
+}
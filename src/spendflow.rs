@@ -8,18 +8,28 @@ use btc_heritage_wallet::{
     Broadcaster, KeyProvider, PsbtSummary,
 };
 
-use crate::utils::ask_user_confirmation;
+use crate::{
+    display::{interactive_println, Displayable, Unsuccessful},
+    exit_status::ExitStatus,
+    psbt_analysis::{FeeLimits, PsbtAnalysis},
+    psbt_io::PsbtOutput,
+    utils::ask_user_confirmation,
+};
 
 #[derive(Debug)]
 pub struct SpendFlow<'a, KP: KeyProvider, B: Broadcaster> {
     psbt: Psbt,
     fingerprints: Option<&'a HashMap<Fingerprint, Vec<String>>>,
+    contacts: Option<&'a HashMap<String, String>>,
+    labels: Option<&'a HashMap<String, String>>,
+    fee_limits: FeeLimits,
     transaction_summary: Option<&'a TransactionSummary>,
     network: Network,
     skip_confirmation: bool,
     display_stage: bool,
     sign_stage: Option<&'a KP>,
     broadcast_stage: Option<&'a B>,
+    psbt_output: Option<&'a PsbtOutput>,
     summary_displayed: bool,
 }
 
@@ -28,12 +38,16 @@ impl<'a, KP: KeyProvider, B: Broadcaster> SpendFlow<'a, KP, B> {
         Self {
             psbt,
             fingerprints: None,
+            contacts: None,
+            labels: None,
+            fee_limits: FeeLimits::default(),
             transaction_summary: None,
             network,
             skip_confirmation: false,
             display_stage: false,
             sign_stage: None,
             broadcast_stage: None,
+            psbt_output: None,
             summary_displayed: false,
         }
     }
@@ -41,6 +55,19 @@ impl<'a, KP: KeyProvider, B: Broadcaster> SpendFlow<'a, KP, B> {
         self.fingerprints = Some(fingerprints);
         self
     }
+    pub fn contacts(mut self, contacts: &'a HashMap<String, String>) -> Self {
+        self.contacts = Some(contacts);
+        self
+    }
+    pub fn labels(mut self, labels: &'a HashMap<String, String>) -> Self {
+        self.labels = Some(labels);
+        self
+    }
+    /// The current fee rate estimate, in sat/vB, used to spot an abnormal fee rate
+    pub fn current_fee_rate(mut self, current_fee_rate: Option<f64>) -> Self {
+        self.fee_limits.current_fee_rate = current_fee_rate;
+        self
+    }
     pub fn transaction_summary(mut self, transaction_summary: &'a TransactionSummary) -> Self {
         self.transaction_summary = Some(transaction_summary);
         self
@@ -69,6 +96,10 @@ impl<'a, KP: KeyProvider, B: Broadcaster> SpendFlow<'a, KP, B> {
         self.broadcast_stage = Some(broadcaster);
         self
     }
+    pub fn psbt_output(mut self, psbt_output: &'a PsbtOutput) -> Self {
+        self.psbt_output = Some(psbt_output);
+        self
+    }
     pub fn set_skip_confirmations(mut self, skip_confirmation: bool) -> Self {
         self.skip_confirmation = skip_confirmation;
         self
@@ -95,7 +126,12 @@ impl<'a, KP: KeyProvider, B: Broadcaster> SpendFlow<'a, KP, B> {
 
         if confirm_sign && !self.confirm_before_sign().await? {
             log::warn!("Signing refused");
-            return Ok(Box::new(self.psbt.to_string()));
+            return interrupted(
+                self.psbt_output,
+                &self.psbt,
+                ExitStatus::Cancelled,
+                "Signing refused",
+            );
         };
 
         if sign {
@@ -103,8 +139,13 @@ impl<'a, KP: KeyProvider, B: Broadcaster> SpendFlow<'a, KP, B> {
                 Ok(_) => (),
                 Err(e) => {
                     log::error!("Signing errored: {e}");
-                    println!("Cannot sign the PSBT ({e})\n\n");
-                    return Ok(Box::new(self.psbt.to_string()));
+                    interactive_println(&format!("Cannot sign the PSBT ({e})\n\n"));
+                    return interrupted(
+                        self.psbt_output,
+                        &self.psbt,
+                        ExitStatus::SigningFailed,
+                        format!("Cannot sign the PSBT ({e})"),
+                    );
                 }
             }
         };
@@ -115,7 +156,12 @@ impl<'a, KP: KeyProvider, B: Broadcaster> SpendFlow<'a, KP, B> {
 
         if confirm_broadcast && !self.confirm_before_broadcast().await? {
             log::warn!("Broadcast refused");
-            return Ok(Box::new(self.psbt.to_string()));
+            return interrupted(
+                self.psbt_output,
+                &self.psbt,
+                ExitStatus::Cancelled,
+                "Broadcast refused",
+            );
         };
 
         let result = if broadcast {
@@ -124,11 +170,18 @@ impl<'a, KP: KeyProvider, B: Broadcaster> SpendFlow<'a, KP, B> {
                 Ok(tx_id) => tx_id,
                 Err(e) => {
                     log::error!("Broadcasting errored: {e}");
-                    println!("Cannot broadcast the PSBT ({e})\n\n");
-                    return Ok(Box::new(psbt.to_string()));
+                    interactive_println(&format!("Cannot broadcast the PSBT ({e})\n\n"));
+                    return interrupted(
+                        self.psbt_output,
+                        &psbt,
+                        ExitStatus::BroadcastFailed,
+                        format!("Cannot broadcast the PSBT ({e})"),
+                    );
                 }
             };
             tx_id.to_string()
+        } else if let Some(psbt_output) = self.psbt_output {
+            return psbt_output.output(&self.psbt);
         } else {
             self.psbt.to_string()
         };
@@ -145,10 +198,25 @@ impl<'a, KP: KeyProvider, B: Broadcaster> SpendFlow<'a, KP, B> {
                 self.fingerprints,
                 self.network,
             ))?;
-            println!("################");
-            println!("# PSBT Summary #");
-            println!("################");
-            println!("{summary}");
+            let mut analysis = PsbtAnalysis::new(
+                &self.psbt,
+                self.fingerprints,
+                self.network,
+                &self.fee_limits,
+            );
+            if let Some(contacts) = self.contacts {
+                analysis.set_contacts(contacts);
+            }
+            if let Some(labels) = self.labels {
+                analysis.set_labels(labels);
+            }
+            interactive_println("################");
+            interactive_println("# PSBT Summary #");
+            interactive_println("################");
+            interactive_println(&analysis.annotate(&summary.to_string()));
+            for warning in analysis.warnings_text() {
+                interactive_println(&warning);
+            }
             self.summary_displayed = true;
         }
         Ok(())
@@ -182,3 +250,18 @@ impl<'a, KP: KeyProvider, B: Broadcaster> SpendFlow<'a, KP, B> {
         Ok(tx_id)
     }
 }
+
+/// The result of a [SpendFlow] interrupted before its end: the PSBT as it is,
+/// written as requested by the [PsbtOutput] if any
+fn interrupted(
+    psbt_output: Option<&PsbtOutput>,
+    psbt: &Psbt,
+    exit_status: ExitStatus,
+    reason: impl Into<String>,
+) -> Result<Box<dyn Displayable>> {
+    let result: Box<dyn Displayable> = match psbt_output {
+        Some(psbt_output) => psbt_output.output(psbt)?,
+        None => Box::new(psbt.to_string()),
+    };
+    Ok(Box::new(Unsuccessful::new(exit_status, reason, result)))
+}
@@ -1,18 +1,31 @@
 use std::{
     collections::HashMap,
-    io::{stdin, stdout, Write},
+    io::{stderr, stdin, stdout, Write},
 };
 
 use btc_heritage_wallet::{
-    btc_heritage::HeritageWalletBackup,
+    btc_heritage::{heritage_wallet::WalletAddress, HeritageWalletBackup},
     errors::{Error, Result},
     heritage_service_api_client::Fingerprint,
-    BoundFingerprint, Database, DatabaseItem, Heir, HeirWallet, Wallet,
+    BoundFingerprint, Database, DatabaseItem, Heir, HeirWallet, OnlineWallet, Wallet,
 };
 
+use crate::display::{output_format, OutputFormat};
+
 pub async fn ask_user_confirmation(prompt: &str) -> Result<bool> {
-    print!("{prompt} Answer \"yes\" or \"no\" (default \"no\"): ");
-    stdout().flush().map_err(|e| {
+    let prompt = format!("{prompt} Answer \"yes\" or \"no\" (default \"no\"): ");
+    // Keep stdout clean of prompts when the result is machine-readable
+    let flush_result = match output_format() {
+        OutputFormat::Text | OutputFormat::Table => {
+            print!("{prompt}");
+            stdout().flush()
+        }
+        OutputFormat::Json => {
+            eprint!("{prompt}");
+            stderr().flush()
+        }
+    };
+    flush_result.map_err(|e| {
         log::error!("Could not display the confirmation prompt: {e}");
         Error::generic(e)
     })?;
@@ -81,6 +94,24 @@ pub async fn get_fingerprints(db: &Database) -> Result<HashMap<Fingerprint, Vec<
     Ok(map)
 }
 
+/// The fee rate last estimated by the online wallet, in sat/vB, None if it is not available
+pub async fn current_fee_rate(wallet: &Wallet) -> Option<f64> {
+    match wallet.get_wallet_status().await {
+        Ok(status) => status
+            .last_fee_rate
+            .map(|fee_rate| fee_rate.as_sat_per_vb() as f64),
+        Err(e) => {
+            log::debug!("Could not get the fee rate estimate of the wallet: {e}");
+            None
+        }
+    }
+}
+
+/// The bare address of a [WalletAddress], without its key origin
+pub fn wallet_address_to_string(wallet_address: &WalletAddress) -> String {
+    wallet_address.address().to_string()
+}
+
 pub(crate) fn parse_heritage_wallet_backup(
     val: &str,
 ) -> core::result::Result<HeritageWalletBackup, serde_json::Error> {